@@ -0,0 +1,119 @@
+use std::{
+    env,
+    fs,
+    path::{Path, PathBuf},
+    io,
+};
+// JSON
+use serde::{Deserialize, Serialize};
+// Errors
+use thiserror::Error;
+
+pub mod profile;
+use profile::{get_active_profile, get_profile_store_path, profile_exists, DEFAULT_PROFILE};
+
+// Environment variable that can point to the notes file
+pub const STORE_ENV_VAR: &str = "NOTES_TRACKER_STORE";
+
+// Folder name used under the users config and data directories
+const APP_DIR: &str = "notes_tracker";
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("There was an I/O error reading the config: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("The config file could not be read: {0}")]
+    SerdeJson(#[from] serde_json::Error),
+
+    #[error("Could not find a home directory to store notes in, use --store or {STORE_ENV_VAR} to set one")]
+    NoDataDir,
+
+    #[error("{0}")]
+    Profile(String),
+}
+
+// User config, read from <config dir>/notes_tracker/config.json
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Config {
+    // Path to the notes file, used instead of profiles if set
+    pub store_path: Option<PathBuf>,
+    // Active profile, None for the default profile
+    pub profile: Option<String>,
+}
+
+// Gets the config file path, None if the system has no config directory
+pub fn get_config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join(APP_DIR).join("config.json"))
+}
+
+// Loads the config file, falling back to the defaults if there isn't one
+pub fn load_config() -> Result<Config, ConfigError> {
+    match get_config_path() {
+        Some(path) if Path::exists(&path) => {
+            let file = fs::read_to_string(path)?;
+            if file.trim().is_empty() {
+                Ok(Config::default())
+            } else {
+                Ok(serde_json::from_str(&file)?)
+            }
+        },
+        _ => Ok(Config::default()),
+    }
+}
+
+// Saves the config file, creating its folder if needed
+pub fn save_config(config: &Config) -> Result<(), ConfigError> {
+    let path = get_config_path().ok_or(ConfigError::NoDataDir)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, serde_json::to_string_pretty(config)?)?;
+    Ok(())
+}
+
+// Default notes file, in the XDG data directory (~/.local/share on linux)
+pub fn default_store_path() -> Result<PathBuf, ConfigError> {
+    get_profile_store_path(DEFAULT_PROFILE)
+}
+
+// Works out which notes file to use. In order of priority:
+// 1. The --store flag
+// 2. The --profile flag
+// 3. The NOTES_TRACKER_STORE environment variable
+// 4. store_path in the config file
+// 5. The active profile in the config file, stored in the XDG data directory
+pub fn resolve_store_path(cli_path: Option<PathBuf>, cli_profile: Option<String>) -> Result<PathBuf, ConfigError> {
+    if let Some(path) = cli_path {
+        return Ok(path);
+    }
+    if let Some(profile) = cli_profile {
+        if !profile_exists(&profile)? {
+            return Err(ConfigError::Profile(format!(
+                "There is no profile named \"{profile}\", create it with \"profile create {profile}\"")));
+        }
+        return get_profile_store_path(&profile);
+    }
+    if let Some(path) = env::var_os(STORE_ENV_VAR).filter(|p| !p.is_empty()) {
+        return Ok(expand_home(PathBuf::from(path)));
+    }
+    if let Some(path) = load_config()?.store_path {
+        return Ok(expand_home(path));
+    }
+    let profile = get_active_profile()?;
+    if !profile_exists(&profile)? {
+        return Err(ConfigError::Profile(format!(
+            "The active profile \"{profile}\" no longer exists, switch with \"profile switch <name>\"")));
+    }
+    get_profile_store_path(&profile)
+}
+
+// Replaces a leading ~ with the users home directory,
+// as config files and quoted env vars don't get shell expansion
+fn expand_home(path: PathBuf) -> PathBuf {
+    match (path.strip_prefix("~"), dirs::home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path,
+    }
+}
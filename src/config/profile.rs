@@ -0,0 +1,151 @@
+// Named profiles, each one a folder holding its own notes and settings.
+// The default profile lives directly in the data directory, so collections
+// from before profiles existed carry on as the default profile
+use std::{fs, path::PathBuf};
+
+use super::{load_config, save_config, ConfigError, APP_DIR};
+use crate::storage::lock;
+
+pub const DEFAULT_PROFILE: &str = "default";
+
+// Gets the folder everything is stored under, e.g. ~/.local/share/notes_tracker
+pub fn get_data_dir() -> Result<PathBuf, ConfigError> {
+    dirs::data_dir()
+        .map(|dir| dir.join(APP_DIR))
+        .ok_or(ConfigError::NoDataDir)
+}
+
+// Folder holding every profile other than the default
+fn get_profiles_dir() -> Result<PathBuf, ConfigError> {
+    Ok(get_data_dir()?.join("profiles"))
+}
+
+// Gets the folder of the given profile.
+// Names are checked here so nothing can point outside the profiles folder
+pub fn get_profile_dir(name: &str) -> Result<PathBuf, ConfigError> {
+    if name == DEFAULT_PROFILE {
+        get_data_dir()
+    } else {
+        validate_profile_name(name)?;
+        Ok(get_profiles_dir()?.join(name))
+    }
+}
+
+// Gets the notes file of the given profile
+pub fn get_profile_store_path(name: &str) -> Result<PathBuf, ConfigError> {
+    Ok(get_profile_dir(name)?.join("notes.json"))
+}
+
+// Checks the profile name can safely be used as a folder name
+pub fn validate_profile_name(name: &str) -> Result<(), ConfigError> {
+    if name.is_empty() {
+        return Err(ConfigError::Profile("Profile names can't be empty".to_string()));
+    }
+    if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err(ConfigError::Profile(format!(
+            "Profile name \"{name}\" can only contain letters, numbers, - and _")));
+    }
+    Ok(())
+}
+
+// Whether a profile with the given name exists. The default always does
+pub fn profile_exists(name: &str) -> Result<bool, ConfigError> {
+    Ok(name == DEFAULT_PROFILE || get_profile_dir(name)?.is_dir())
+}
+
+// Gets the names of every profile, default first and the rest in alphabetical order
+pub fn list_profiles() -> Result<Vec<String>, ConfigError> {
+    let mut profiles: Vec<String> = vec![];
+    let profiles_dir = get_profiles_dir()?;
+    if profiles_dir.is_dir() {
+        for entry in fs::read_dir(profiles_dir)? {
+            let entry = entry?;
+            if entry.path().is_dir() {
+                profiles.push(entry.file_name().to_string_lossy().to_string());
+            }
+        }
+    }
+    profiles.sort();
+    profiles.insert(0, DEFAULT_PROFILE.to_string());
+    Ok(profiles)
+}
+
+// Gets the profile used when none is given on the command line
+pub fn get_active_profile() -> Result<String, ConfigError> {
+    Ok(load_config()?.profile.unwrap_or(DEFAULT_PROFILE.to_string()))
+}
+
+// Saves the profile to use from now on
+pub fn set_active_profile(name: &str) -> Result<(), ConfigError> {
+    if !profile_exists(name)? {
+        return Err(ConfigError::Profile(format!("There is no profile named \"{name}\"")));
+    }
+    let mut config = load_config()?;
+    config.profile = (name != DEFAULT_PROFILE).then(|| name.to_string());
+    save_config(&config)
+}
+
+// Creates an empty profile, its notes file is made when it's first loaded
+pub fn create_profile(name: &str) -> Result<(), ConfigError> {
+    validate_profile_name(name)?;
+    if profile_exists(name)? {
+        return Err(ConfigError::Profile(format!("A profile named \"{name}\" already exists")));
+    }
+    fs::create_dir_all(get_profile_dir(name)?)?;
+    Ok(())
+}
+
+// Renames a profile, keeping it active if it was
+pub fn rename_profile(name: &str, new_name: &str) -> Result<(), ConfigError> {
+    validate_profile_name(new_name)?;
+    if name == DEFAULT_PROFILE || new_name == DEFAULT_PROFILE {
+        return Err(ConfigError::Profile("The default profile can't be renamed".to_string()));
+    }
+    if !profile_exists(name)? {
+        return Err(ConfigError::Profile(format!("There is no profile named \"{name}\"")));
+    }
+    if profile_exists(new_name)? {
+        return Err(ConfigError::Profile(format!("A profile named \"{new_name}\" already exists")));
+    }
+    fs::rename(get_profile_dir(name)?, get_profile_dir(new_name)?)?;
+    if get_active_profile()? == name {
+        set_active_profile(new_name)?;
+    }
+    Ok(())
+}
+
+// Deletes a profile along with all of its notes and settings.
+// The default and active profiles can't be deleted
+pub fn delete_profile(name: &str) -> Result<(), ConfigError> {
+    if name == DEFAULT_PROFILE {
+        return Err(ConfigError::Profile("The default profile can't be deleted".to_string()));
+    }
+    if !profile_exists(name)? {
+        return Err(ConfigError::Profile(format!("There is no profile named \"{name}\"")));
+    }
+    if get_active_profile()? == name {
+        return Err(ConfigError::Profile(format!(
+            "\"{name}\" is the active profile, switch to another before deleting it")));
+    }
+    if lock::is_locked(&get_profile_store_path(name)?)? {
+        return Err(ConfigError::Profile(format!(
+            "\"{name}\" is open in another tracker, close it before deleting the profile")));
+    }
+    fs::remove_dir_all(get_profile_dir(name)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_cant_leave_the_profiles_folder() {
+        for name in ["..", ".", "/", "", "work/..", "../work", "C:\\work"] {
+            assert!(matches!(get_profile_dir(name), Err(ConfigError::Profile(_))), "{name:?}");
+            assert!(matches!(profile_exists(name), Err(ConfigError::Profile(_))), "{name:?}");
+            assert!(matches!(delete_profile(name), Err(ConfigError::Profile(_))), "{name:?}");
+        }
+        assert!(validate_profile_name("work-notes_2").is_ok());
+    }
+}
@@ -1,23 +1,29 @@
-use std::{collections::HashMap, ffi::OsStr, io::{self, Write}, process};
+use std::{collections::{BTreeMap, BTreeSet, HashMap, HashSet}, ffi::OsStr, fs, io::{self, Write}, iter, path::{Path, PathBuf}, process, sync::{atomic::{AtomicBool, Ordering}, mpsc, Arc, Mutex, TryLockError}, thread, time::{Duration, Instant}};
 
 // Crates
-use dialoguer::{theme::ColorfulTheme, Input, Select};
+use clap::{Parser, Subcommand};
+use dialoguer::{theme::ColorfulTheme, Input, MultiSelect, Select};
 use itertools::Itertools;
 use chrono::Local;
+use notify_debouncer_mini::{new_debouncer, notify::RecursiveMode};
 use thiserror::Error;
 use walkdir::WalkDir;
 
 // Mods
+pub mod config;
 pub mod storage;
 pub mod tracker;
+use config::{profile::*, ConfigError};
 use storage::*;
 use tracker::*;
+use tracker::scheduler::SchedulerKind;
+use tracker::tags::{parse_tags, TagFilter};
 
 
 // Choice menus
-const YES_NO_CHOICES: &'static [&str;2] = &["YES", "NO"];
+const YES_NO_CHOICES: &[&str;2] = &["YES", "NO"];
 
-const MAIN_MENU_CHOICES: &'static [&str;8] = &[
+const MAIN_MENU_CHOICES: &[&str;14] = &[
     "Add Note",
     "View Notes",
     "Edit Note",
@@ -25,43 +31,225 @@ const MAIN_MENU_CHOICES: &'static [&str;8] = &[
     "Generate Review",
     "Generate Notes",
     "Remove Notes Using File",
+    "Undo",
+    "Redo",
+    "Trash",
+    "Settings",
+    "Profiles",
+    "Backups",
     "Quit"
     ];
 
+// How often a watch tries to sync again while the notes are open in another tracker
+const WATCH_RETRY: Duration = Duration::from_secs(5);
+// How often a watch checks whether ctrl-c asked it to stop
+const WATCH_TICK: Duration = Duration::from_millis(200);
+// Set by ctrl-c while watching
+static STOP_WATCHING: AtomicBool = AtomicBool::new(false);
+// Set once ctrl-c starts ending the process, by the handler or an interrupted prompt
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
 // Boolean flag to indicate whether the user want to clear the screen after inputs or not
 static mut CLEAR: bool = false;
 
 #[derive(Error, Debug)]
 enum MainError {
     #[error("{0}")]
-    DriverError(String),
+    Driver(String),
+
+    #[error("{0}")]
+    Tracker(#[from] TrackerError),
 
     #[error("{0}")]
-    TrackerError(#[from] TrackerError),
+    Storage(#[from] StorageError),
 
     #[error("{0}")]
-    StorageError(#[from] StorageError)
+    Config(#[from] ConfigError),
+
+    // Ctrl-c was pressed at a prompt
+    #[error("Interrupted")]
+    Interrupted,
+}
+
+impl From<dialoguer::Error> for MainError {
+    fn from(e: dialoguer::Error) -> Self {
+        match e {
+            dialoguer::Error::IO(e) if e.kind() == io::ErrorKind::Interrupted => MainError::Interrupted,
+            dialoguer::Error::IO(e) => MainError::Driver(format!("Could not read input due to error {e}")),
+        }
+    }
+}
+
+// Command line arguments
+#[derive(Parser, Debug)]
+#[command(version, about = "Tracks your notes and tells you when to review them")]
+struct Cli {
+    #[arg(long, value_name = "FILE",
+        help = format!("Notes file to use, overrides ${} and the config file", config::STORE_ENV_VAR))]
+    store: Option<PathBuf>,
+
+    #[arg(long, short, value_name = "NAME", help = "Profile to open for this run, without changing the active profile")]
+    profile: Option<String>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    #[command(about = "Copy the notes into a new store, e.g. notes.db to move to SQLite")]
+    Convert {
+        #[arg(help = "New notes file, .db/.sqlite/.sqlite3 use SQLite and anything else JSON")]
+        to: PathBuf,
+    },
+    #[command(about = "Undo the last changes made to the notes")]
+    Undo {
+        #[arg(long, default_value_t = 1, help = "Number of changes to undo")]
+        steps: usize,
+    },
+    #[command(about = "Redo changes that were undone")]
+    Redo {
+        #[arg(long, default_value_t = 1, help = "Number of changes to redo")]
+        steps: usize,
+    },
+    #[command(about = "List the changes that can be undone and redone")]
+    History,
+    #[command(about = "List, restore and purge removed notes")]
+    Trash {
+        #[command(subcommand)]
+        action: TrashCommand,
+    },
+    #[command(about = "Watch markdown directories, syncing their notes whenever files are created, edited or deleted")]
+    Watch {
+        #[arg(required = true, help = "Directories to watch")]
+        dirs: Vec<PathBuf>,
+        #[arg(long, value_parser = clap::value_parser!(u8).range(1..=6),
+            help = "Smallest header to make notes from, 1 for H1 through to 6 for H6. Defaults to the one the directory was synced with, or 6")]
+        level: Option<u8>,
+        #[arg(long, help = "Prefix added to the names of new notes. Defaults to the one the directory was synced with")]
+        prefix: Option<String>,
+        #[arg(long, help = "Tags for new notes, seperated by spaces. Defaults to the ones the directory was synced with")]
+        tags: Option<String>,
+        #[arg(long, help = "Archive notes whose header is removed, rather than only logging them")]
+        archive: bool,
+        #[arg(long, default_value_t = 2000, value_name = "MS", help = "Milliseconds to let changes settle before syncing")]
+        debounce: u64,
+    },
+    #[command(about = "Manage profiles, each with their own notes and settings")]
+    Profile {
+        #[command(subcommand)]
+        action: ProfileCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum TrashCommand {
+    #[command(about = "List the notes in the trash")]
+    List,
+    #[command(about = "Put a trashed note back, the most recently removed if several share the name")]
+    Restore {
+        name: String,
+        #[arg(long = "as", value_name = "NAME", help = "Restore under a new name, e.g. if the old one is taken")]
+        new_name: Option<String>,
+    },
+    #[command(about = "Permanently delete trashed notes")]
+    Purge {
+        #[arg(required_unless_present = "all", help = "Purge every trashed note with this name")]
+        name: Option<String>,
+        #[arg(long, conflicts_with = "name", help = "Purge everything in the trash")]
+        all: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ProfileCommand {
+    #[command(about = "List all profiles")]
+    List,
+    #[command(about = "Create a new profile")]
+    Create { name: String },
+    #[command(about = "Set the profile opened by default")]
+    Switch { name: String },
+    #[command(about = "Rename a profile")]
+    Rename { name: String, new_name: String },
+    #[command(about = "Delete a profile and all of its notes")]
+    Delete {
+        name: String,
+        #[arg(long, help = "Delete without asking for confirmation")]
+        yes: bool,
+    },
 }
     
 fn main() {
+    let cli = Cli::parse();
+
+    // Runs one off commands without opening the menu
+    if let Some(command) = cli.command {
+        match run_command(command, cli.store, cli.profile) {
+            Ok(message) => {
+                println!("{}", green_wrap!(message));
+                process::exit(0);
+            },
+            Err(e) => {
+                println!("{}", red_wrap!(e));
+                process::exit(1);
+            },
+        }
+    }
+
+    // Works out where the notes are kept, so the same file is used
+    // no matter which folder the tracker is started from
+    if let Err(e) = resolve_store(cli.store, cli.profile) {
+        println!("Could not find the notes file due to error {}\nEnding Process...", e);
+        process::exit(1);
+    }
+
     // Loads in notes
-    println!("Loading...");
+    println!("Loading notes from {}...", bold_wrap!(get_store_path().display()));
 
-    // Main map of notes used throughout.
+    // Main map of notes used throughout, along with the store it's saved to.
     // If it cannot be loaded, the process is ended :(
-    let mut note_map: HashMap<String, Note>;
-    match load_map() {
-        Ok(map) => note_map = map,
+    let (mut map_store, note_map) = match MapStore::open(false) {
+        Ok(opened) => opened,
+        // Another tracker has the notes open, so they can only be looked at
+        Err(TrackerError::StorageErr(e @ StorageError::Locked { .. })) => {
+            let read_only = select_wrapper(
+                format!("{} {}\nOpen the notes read only? Changes won't be saved", red_wrap!("Warning:"), e).as_str(),
+                YES_NO_CHOICES).unwrap_or_else(|e| exit_on_prompt_error(e));
+            if YES_NO_CHOICES[read_only] != "YES" {
+                println!("Close the other tracker and try again\nEnding Process...");
+                process::exit(1);
+            }
+            match MapStore::open(true) {
+                Ok(opened) => opened,
+                Err(e) => {
+                    println!("Could not load map due to error {}\nEnding Process...",e);
+                    process::exit(1);
+                },
+            }
+        },
         Err(e) => {
             println!("Could not load map due to error {}\nEnding Process...",e);
             process::exit(1);
         },
-    }
+    };
+
+    // Collection settings, such as which scheduler picks the reviews
+    let mut settings: Settings = match load_settings() {
+        Ok(settings) => settings,
+        Err(e) => {
+            println!("Could not load settings due to error {}\nEnding Process...",e);
+            process::exit(1);
+        },
+    };
+
+    // Backs up the notes as they were at the start of the session
+    io_create_backup(&map_store, &settings);
+    io_purge_old_trash(&mut map_store, &settings);
 
     // Enable screen clearing
     let clear_choice = select_wrapper(
         format!("Enable screen clearning\n{} - Wipes current terminal", red_wrap!("Warning")).as_str(),
-        YES_NO_CHOICES);
+        YES_NO_CHOICES).unwrap_or_else(|e| exit_on_prompt_error(e));
     // Unsafe as due to modification of mutable static
     // This is the only time theres any change so its chill
     unsafe {
@@ -71,53 +259,89 @@ fn main() {
         }
     }
 
+    // Shared with the ctrl-c handler, which saves the notes before exiting.
+    // Only locked while an action runs, so between actions the handler saves anything still unsaved,
+    // e.g. after a save failed. During an action only that action is lost.
+    // Ctrl-c at a prompt errors the prompt instead, which ends the process the same way
+    let session = Arc::new(Mutex::new(Session { map_store, note_map }));
+    let handler_session = Arc::clone(&session);
+    let interrupted = ctrlc::set_handler(move || {
+        match handler_session.try_lock() {
+            Ok(mut session) => exit_interrupted(Some(&mut session)),
+            Err(TryLockError::Poisoned(e)) => exit_interrupted(Some(&mut e.into_inner())),
+            Err(TryLockError::WouldBlock) => exit_interrupted(None),
+        }
+    });
+    if let Err(e) = interrupted {
+        println!("{} Could not listen for ctrl-c due to error {}", red_wrap!("Warning:"), e);
+    }
+
     // Main Loop
     clear_screen();
+    let mut warned_read_only = false;
     loop {
-        // Saves map at the start of every loop, this might be changed later
-        // depending on performance costs.
-        // Ends process if it cannot sync (it always should if it can at the start)
-        match sync_map(note_map) {
-            Ok(map) => note_map = map,
-            Err(e) => {
-                println!("Could not save and sync the map due to error{}\nEnding Process...",e);
-                process::exit(1);
-            },
-        }
-
         // Gets main menu choice from user
-        let menu_choice = select_wrapper(
-            "Main Menu",
-            MAIN_MENU_CHOICES
-        );
+        let menu_choice = match select_wrapper("Main Menu", MAIN_MENU_CHOICES) {
+            Ok(choice) => choice,
+            // No action is running, so anything unsaved can be saved
+            Err(MainError::Interrupted) => exit_interrupted(Some(&mut session.lock().unwrap_or_else(|e| e.into_inner()))),
+            Err(e) => exit_on_prompt_error(e),
+        };
     
         // Reset Screen after selection, leaving the result message from the last
         // message on screen for the user
         clear_screen();
+        let mut session = session.lock().unwrap_or_else(|e| e.into_inner());
+        let Session { map_store, note_map } = &mut *session;
         match MAIN_MENU_CHOICES[menu_choice] {
             "Add Note" => {                
-                handle_map_operation(&mut note_map, |m| io_add_note(m));
+                handle_map_operation(note_map, io_add_note);
             },
             "Remove Note" => {
-                handle_map_operation(&mut note_map, |m| io_remove_note(m));
+                handle_map_operation(note_map, io_remove_note);
             }
             "View Notes" => {
-                handle_map_operation(&mut note_map, |m| io_view_map(&m));
+                handle_map_operation(note_map, |m| io_view_map(m));
             },
             "Edit Note" => {
-                handle_map_operation(&mut note_map, |m| io_edit_note_map(m));
+                handle_map_operation(note_map, io_edit_note_map);
             },
             "Generate Review" => {
-                handle_map_operation(&mut note_map, |m| io_generate_review(m));
+                handle_map_operation(note_map, |m| io_generate_review(m, &settings));
             },
             "Generate Notes" => {
-                handle_map_operation(&mut note_map, |m| io_generate_notes(m));
+                handle_map_operation(note_map, |m| io_generate_notes(m, &mut settings, map_store.is_read_only()));
             },
             "Remove Notes Using File" => {
-                handle_map_operation(&mut note_map, |m| io_remove_notes_wth_file(m));
+                handle_map_operation(note_map, io_remove_notes_wth_file);
+            },
+            "Undo" => {
+                handle_map_operation(note_map, |m| io_undo(m, map_store, true));
+            },
+            "Redo" => {
+                handle_map_operation(note_map, |m| io_undo(m, map_store, false));
+            },
+            "Trash" => {
+                handle_map_operation(note_map, |m| io_trash(m, map_store));
+            },
+            "Settings" => {
+                handle_map_operation(note_map, |_| io_edit_settings(&mut settings, map_store.is_read_only()));
+            },
+            "Profiles" => {
+                handle_map_operation(note_map, |m| io_profiles(m, map_store, &mut settings));
+            },
+            "Backups" => {
+                handle_map_operation(note_map, |m| io_backups(m, map_store, &settings));
             },
             "Quit" => {            
                 clear_screen(); // Clear screen and reset cursor before exiting
+                if map_store.is_read_only() {
+                    process::exit(0);
+                }
+                if let Err(e) = map_store.flush(note_map, "Unsaved changes") {
+                    println!("Could not save notes due to error {}\nEnding Process...", e);
+                    process::exit(1);
+                }
                 process::exit(0);
             },
             _ => {
@@ -126,19 +350,190 @@ fn main() {
                 process::exit(0);
             },
         };
+
+        // Read only notes are never saved, which is said once when they're first changed
+        if map_store.is_read_only() {
+            if !warned_read_only && map_store.is_dirty(note_map) {
+                println!("{} The notes are open read only, so changes won't be saved", red_wrap!("Warning:"));
+                warned_read_only = true;
+            }
+            continue;
+        }
+        // Only the notes changed by the action are written, journaled as one step to undo
+        if let Err(e) = map_store.flush(note_map, MAIN_MENU_CHOICES[menu_choice]) {
+            println!("{} Could not save notes due to error {}", red_wrap!("Warning:"), e);
+        }
     }
 }
 
-fn sync_map(note_map: HashMap<String, Note>) -> Result<HashMap<String, Note>, MainError>{
-    match save_map(note_map) {
-        Err(e) => Err(MainError::DriverError(e.to_string())),
-        _ => match load_map() {
-                Ok(m) => Ok(m),
-                Err(e) => Err(MainError::DriverError(
-                format!("Error: Could not load note data, check config file and json file. \nError {e}"
-                ))),
+// The open notes and the store they're saved to
+struct Session {
+    map_store: MapStore,
+    note_map: NoteMap,
+}
+
+// Ends the process after ctrl-c, saving the notes when given them. They aren't given
+// while an action is running, as it may have been stopped part way through.
+// The ctrl-c handler and an interrupted prompt can both get here, so the second waits for the first to exit
+fn exit_interrupted(session: Option<&mut Session>) -> ! {
+    if INTERRUPTED.swap(true, Ordering::SeqCst) {
+        loop {
+            thread::park();
         }
     }
+    // Show the cursor again in case a prompt hid it
+    print!("\x1B[?25h");
+    match session {
+        Some(Session { map_store, note_map }) => {
+            if !map_store.is_read_only() {
+                if let Err(e) = map_store.flush(note_map, "Unsaved changes") {
+                    println!("\nCould not save notes due to error {}", e);
+                    process::exit(1);
+                }
+            }
+            println!("\nInterrupted, all changes are saved");
+        },
+        None => {
+            // Waits for any save in progress so the store is never left half written
+            let _lock = FLUSH_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+            println!("\nInterrupted, all changes up to the last finished action are saved");
+        },
+    }
+    process::exit(130);
+}
+
+// Ends the process when a prompt outside of an action fails
+fn exit_on_prompt_error(e: MainError) -> ! {
+    match e {
+        MainError::Interrupted => exit_interrupted(None),
+        e => {
+            println!("Could not read input due to error {}\nEnding Process...", e);
+            process::exit(1);
+        },
+    }
+}
+
+// Resolves the notes file and sets it as the one used by storage
+fn resolve_store(cli_path: Option<PathBuf>, cli_profile: Option<String>) -> Result<(), MainError> {
+    let store_path = config::resolve_store_path(cli_path, cli_profile)?;
+    // Older versions always used notes.json in the current folder,
+    // so point anyone with one there towards it instead of silently starting fresh
+    let local_notes = PathBuf::from("notes.json");
+    if !store_path.exists() && local_notes.exists() && store_path != local_notes {
+        println!("{} Found notes.json in the current folder, but notes are now loaded from {}\nUse {} or ${} to keep using it",
+            red_wrap!("Note:"),
+            bold_wrap!(store_path.display()),
+            bold_wrap!("--store notes.json"),
+            config::STORE_ENV_VAR);
+    }
+    set_store_path(store_path);
+    Ok(())
+}
+
+// Runs a command given on the command line
+fn run_command(command: Command, cli_path: Option<PathBuf>, cli_profile: Option<String>) -> Result<String, MainError> {
+    match command {
+        Command::Convert { to } => {
+            resolve_store(cli_path, cli_profile)?;
+            if to.exists() {
+                return Err(MainError::Driver(format!("{} already exists", bold_wrap!(to.display()))));
+            }
+            let notes = store::open_store(&get_store_path(), true)?.load()?;
+            store::open_store(&to, false)?.save(&notes)?;
+            Ok(format!("Copied {} notes from {} to {}\nUse --store or the config file to start using it",
+                notes.len(), bold_wrap!(get_store_path().display()), bold_wrap!(to.display())))
+        },
+        Command::Undo { steps } | Command::Redo { steps } => {
+            let undo = matches!(command, Command::Undo { .. });
+            resolve_store(cli_path, cli_profile)?;
+            let (mut map_store, mut note_map) = MapStore::open(false)?;
+            let mut messages: Vec<String> = vec![];
+            for _ in 0..steps {
+                match io_step(&mut note_map, &mut map_store, undo) {
+                    Ok(message) => messages.push(message),
+                    // Report what was done before running out of steps
+                    Err(e) if !messages.is_empty() => {
+                        messages.push(e.to_string());
+                        break;
+                    },
+                    Err(e) => return Err(e),
+                }
+            }
+            Ok(messages.join("\n"))
+        },
+        Command::Watch { dirs, level, prefix, tags, archive, debounce } => {
+            resolve_store(cli_path, cli_profile)?;
+            let roots = resolve_watch_options(&dirs, level.map(usize::from), prefix, tags.as_deref().map(parse_tags))?;
+            watch_directories(&roots, archive, Duration::from_millis(debounce))
+        },
+        Command::History => {
+            resolve_store(cli_path, cli_profile)?;
+            let (map_store, _) = MapStore::open(true)?;
+            Ok(format_history(&map_store))
+        },
+        Command::Trash { action } => {
+            resolve_store(cli_path, cli_profile)?;
+            let read_only = matches!(action, TrashCommand::List);
+            let (mut map_store, mut note_map) = MapStore::open(read_only)?;
+            match action {
+                TrashCommand::List => Ok(format_trash(map_store.trashed())),
+                TrashCommand::Restore { name, new_name } => {
+                    let index = map_store.trashed().iter()
+                        .rposition(|trashed| name_key(&trashed.note.name) == name_key(&name))
+                        .ok_or(MainError::Driver(format!("No note named {} is in the trash", bold_wrap!(name))))?;
+                    let name = map_store.restore_trashed(&mut note_map, index, new_name)?;
+                    Ok(format!("Restored {} from the trash", bold_wrap!(name)))
+                },
+                TrashCommand::Purge { name, all: _ } => {
+                    let indices = map_store.trashed().iter().enumerate()
+                        .filter(|(_, trashed)| name.as_ref()
+                            .is_none_or(|name| name_key(&trashed.note.name) == name_key(name)))
+                        .map(|(index, _)| index)
+                        .collect_vec();
+                    if indices.is_empty() {
+                        return Err(MainError::Driver("No matching notes are in the trash".to_string()));
+                    }
+                    let purged = map_store.purge_trashed(&indices)?;
+                    Ok(format!("Permanently deleted {purged} notes from the trash"))
+                },
+            }
+        },
+        Command::Profile { action } => match action {
+            ProfileCommand::List => {
+                let active = get_active_profile()?;
+                Ok(list_profiles()?.into_iter()
+                    .map(|name| if name == active { format!("{name} (active)") } else { name })
+                    .join("\n"))
+            },
+            ProfileCommand::Create { name } => {
+                create_profile(&name)?;
+                Ok(format!("Created profile {}", bold_wrap!(name)))
+            },
+            ProfileCommand::Switch { name } => {
+                set_active_profile(&name)?;
+                Ok(format!("Switched to profile {}", bold_wrap!(name)))
+            },
+            ProfileCommand::Rename { name, new_name } => {
+                rename_profile(&name, &new_name)?;
+                Ok(format!("Renamed profile {} to {}", bold_wrap!(name), bold_wrap!(new_name)))
+            },
+            ProfileCommand::Delete { name, yes } => {
+                let sure = match yes {
+                    true => 0,
+                    false => select_wrapper(
+                        format!("Delete profile {} and all of its notes?", bold_wrap!(name)).as_str(),
+                        YES_NO_CHOICES)?,
+                };
+                match YES_NO_CHOICES[sure] {
+                    "YES" => {
+                        delete_profile(&name)?;
+                        Ok(format!("Deleted profile {}", bold_wrap!(name)))
+                    },
+                    _ => Err(MainError::Driver("No profile was deleted".to_string())),
+                }
+            },
+        },
+    }
 }
 
 // Restore cursor to the saved position and clear everything below
@@ -156,31 +551,43 @@ fn clear_screen() {
 
 // Handles and formats the result messages from main loop operations
 // Keeps main loop cleaner
-fn handle_map_operation<F>(note_map: &mut HashMap<String, Note>, operation: F) 
+fn handle_map_operation<F>(note_map: &mut NoteMap, operation: F) 
 where 
-    F: Fn(&mut HashMap<String, Note>) -> Result<String, MainError>
+    F: FnOnce(&mut NoteMap) -> Result<String, MainError>
 { 
     match operation(note_map) {
         Ok(message) => {
             println!("{}{}{}", ASCII["GREEN"], message, ASCII["RESET"]);
-        },                    
+        },
+        Err(MainError::Interrupted) => exit_interrupted(None),
         Err(e) => println!("{} {} {}",ASCII["RED"], e, ASCII["RESET"]),
     };
 }
 
-// Prints out each note in map, formatted along with note details
-fn io_view_map(note_map: &HashMap<String, Note>) -> Result<String, MainError>{
+// Prints out each note in map, formatted along with note details.
+// Notes are shown as a tree, each indented under the note it sits under
+fn io_view_map(note_map: &NoteMap) -> Result<String, MainError>{
     io_handle_empty_map(note_map)?;
+    let filter = io_get_tag_filter()?;
     println!("{}",bold_wrap!("...Notes..."));
-    for key in note_map.keys().sorted(){
-        println!("Note {} 
-\tReviewed: {} times.
-\tLast reviewed: {},
-\tTime Since: {}",
-bold_wrap!(note_map[key].name),
-bold_wrap!(note_map[key].freq),
-bold_wrap!(format_time_for_output(&note_map[key].last_accessed)),
-bold_wrap!(format_time_since(&note_map[key].last_accessed).unwrap())
+    // Notes whose parent is filtered out are shown at the top
+    let filtered = note_map.filtered(|n| filter.matches_note(n));
+    for (depth, note) in filtered.tree() {
+        let indent = "    ".repeat(depth);
+        println!("{indent}Note {} 
+{indent}\tTags: {}
+{indent}\tLinks: {}
+{indent}\tReviewed: {} times.
+{indent}\tLast reviewed: {},
+{indent}\tTime Since: {}
+{indent}\tLast outcome: {}",
+bold_wrap!(if note.archived { format!("{} (archived)", note.name) } else { note.name.clone() }),
+bold_wrap!(format_tags(&note.tags)),
+bold_wrap!(format_links(note_map, &note.links)),
+bold_wrap!(note.freq),
+bold_wrap!(format_time_for_output(&note.last_accessed)),
+bold_wrap!(format_time_since(&note.last_accessed).unwrap_or_else(|e| e.to_string())),
+bold_wrap!(note.last_outcome().map_or("None".to_string(), |o| o.to_string()))
         );
     }   
     Ok("".to_string())
@@ -188,55 +595,74 @@ bold_wrap!(format_time_since(&note_map[key].last_accessed).unwrap())
 
 // Input/Output options and handling for generating notes from markdown directorys,
 // markdown files or .txt files
-fn io_generate_notes(note_map: &mut HashMap<String, Note>) -> Result<String, MainError> {
-    let choices = ["Markdown directory", "Markdown file (.md)", "Text file (.txt)"];    
-    let choice = select_wrapper("Select where you would like to generate new notes from", &choices);    
+fn io_generate_notes(note_map: &mut NoteMap, settings: &mut Settings, read_only: bool) -> Result<String, MainError> {
+    let choices = ["Markdown directory", "Sync markdown directory", "Obsidian vault", "Markdown file (.md)", "Text file (.txt)"];    
+    let choice = select_wrapper("Select where you would like to generate new notes from", &choices)?;    
 
     match choices[choice] {
         "Markdown directory" => { 
             // List of all markdown files found, if emtpy, none found. Maybe invalid root name.
             let mut files_found: Vec<String> = Vec::new();
-            let root = io_get_file_path("");            
-            for entry in WalkDir::new(root).into_iter().filter_map(Result::ok) {
+            let mut imports: Vec<TreeImport> = Vec::new();
+            let root = io_get_file_path("")?;            
+            let tags = io_get_tags("Enter tags for every new note, seperated by spaces (optional)\nFile name, folder and front matter tags are added too", &BTreeSet::new())?;
+            for entry in WalkDir::new(&root).into_iter().filter_map(Result::ok) {
                 let path = entry.path();
                 if path.is_file() {                    
-                    match path.extension().and_then(OsStr::to_str) {
-                        Some("md") => {
-                            if let Ok(_) = io_get_notes_from_markdown(path.to_str().unwrap().to_string(), note_map) {
+                    if let Some("md") = path.extension().and_then(OsStr::to_str) {
+                        let mut file_tags = tags.clone();
+                        file_tags.extend(folder_tags(path, Path::new(&root)));
+                        match io_read_tree_import(path.to_str().unwrap(), file_tags) {
+                            Ok(import) => {
                                 files_found.push(path.file_name().unwrap().to_str().unwrap().to_string());
-                            }
-                        },
-                        _ => (),
-                    };
+                                imports.push(import);
+                            },
+                            Err(MainError::Interrupted) => return Err(MainError::Interrupted),
+                            // Files that can't be read are skipped
+                            Err(_) => {},
+                        }
+                    }
                 }
             }
             // If not dir is found with given file path
-            if !files_found.is_empty() {
-                Ok(format!("Notes added from files:\n{}",files_found.join("\n")))
-            } else {
-                Err(MainError::DriverError("Could not Find directory".to_string()))
+            if files_found.is_empty() {
+                return Err(MainError::Driver("Could not Find directory".to_string()));
+            }
+            match io_import_trees(&imports, note_map)? {
+                Some(trees) => Ok(format!("{} new notes added from files:\n{}",
+                    trees.iter().map(|tree| tree.added.len()).sum::<usize>(), files_found.join("\n"))),
+                None => Err(MainError::Driver("Cancelled, no notes were added".to_string())),
             }
         },
+        "Sync markdown directory" => io_sync_directory(note_map, settings, read_only),
+        "Obsidian vault" => io_import_vault(note_map),
         // Make down and text use the same code but with
         "Markdown file (.md)" => {
             // Gets file path
-            let file_path = io_get_file_path("");
-            match io_get_notes_from_markdown(file_path, note_map) {
-                Ok(msg) => Ok(msg),
-                Err(e) => Err(e),
+            let file_path = io_get_file_path("")?;
+            let tags = io_get_tags("Enter tags for the new notes, seperated by spaces (optional)\nFile name and front matter tags are added too", &BTreeSet::new())?;
+            let import = io_read_tree_import(&file_path, tags)?;
+            match io_import_trees(&[import], note_map)? {
+                Some(trees) => Ok(format!("{} new notes successfully added from file {}",
+                    trees.iter().map(|tree| tree.added.len()).sum::<usize>(), bold_wrap!(file_path))),
+                None => Err(MainError::Driver("Cancelled, no notes were added".to_string())),
             }
         },
         "Text file (.txt)" => {
             // Gets file path
-            let file_path = io_get_file_path(".txt");
+            let file_path = io_get_file_path(".txt")?;
             // Gets note names
             match get_note_names_from_file(file_path.as_str()) {
                 Ok(note_names) => {
-                    let prefix = io_get_prefix();
-                    io_create_new_notes_from_vec(prefix, note_names, note_map);      
-                    Ok(format!("New Notes successfully added from file {}", bold_wrap!(file_path)))
+                    let prefix = io_get_prefix()?;
+                    let mut tags = io_get_tags("Enter tags for the new notes, seperated by spaces (optional)\nThe file name is added too", &BTreeSet::new())?;
+                    tags.extend(file_name_tag(&file_path));
+                    match io_create_new_notes_from_vec(prefix, note_names, &tags, note_map)? {
+                        Some(added) => Ok(format!("{} new notes successfully added from file {}", added, bold_wrap!(file_path))),
+                        None => Err(MainError::Driver("Cancelled, no notes were added".to_string())),
+                    }
                 },
-                Err(e) => Err(MainError::DriverError(format!(
+                Err(e) => Err(MainError::Driver(format!(
                     "Could not get names, due to error: {e}"))),
             }
         },
@@ -250,48 +676,49 @@ fn io_generate_notes(note_map: &mut HashMap<String, Note>) -> Result<String, Mai
 // Gets and adds a new Note to the note name
 // Requests a name from the user, validates the name
 // and creates as new Note, adding it to the map.
-fn io_add_note(note_map: &mut HashMap<String, Note>) -> Result<String, MainError> {            
+fn io_add_note(note_map: &mut NoteMap) -> Result<String, MainError> {            
     // Notes values
     let freq: u16 = 0;
-    let last_accessed = Local::now().to_string();    
+    let last_accessed = Local::now();
     let name: String = Input::new()
         .with_prompt("Enter the New Notes Name")
         .validate_with(|input: &String| -> Result<(), &str> {
             // Check Note of same is not already in map
-            match note_map.contains_key(&input.to_lowercase()) {
-                false => Ok(()),
-                true => Err("Note with same name already added\nTry Again"),
+            match clean_name(input) {
+                Err(_) => Err("Note names can't be empty\nTry Again"),
+                Ok(name) if note_map.contains_name(&name) => Err("Note with same name already added\nTry Again"),
+                Ok(_) => Ok(()),
             }
         })
-        .interact()
-        .unwrap();
+        .interact()?;
 
     // Gives user an out incase they're filled with a deep regret over
     // their note choice
-    let sure = select_wrapper("Add Note?", YES_NO_CHOICES);
+    let sure = select_wrapper("Add Note?", YES_NO_CHOICES)?;
 
     match YES_NO_CHOICES[sure] {
         "YES" => {
-            note_map.insert(name.clone(), Note::new(name, freq, last_accessed));
+            let mut note = Note::new(name, freq, last_accessed);
+            note.tags = io_get_tags("Enter tags, seperated by spaces (optional)", &BTreeSet::new())?;
+            note_map.insert(note)?;
             Ok("Success! Note Added".to_string())    
         },
-        _ => Err(MainError::DriverError("No Note was added".to_string()))
+        _ => Err(MainError::Driver("No Note was added".to_string()))
     }
 }
 
 
 // Gets and removes a Note from the map
-fn io_remove_note(note_map: &mut HashMap<String, Note>) -> Result<String, MainError> {
+fn io_remove_note(note_map: &mut NoteMap) -> Result<String, MainError> {
     // Handle case where map is empty
     io_handle_empty_map(note_map)?;
     let name: String = Input::new()
         .with_prompt("Enter Notes Name to be Removed")
-        .interact()
-        .unwrap();
+        .interact()?;
 
     // Gives user an out incase they're filled with a deep regret over
     // their note choice
-    let sure = select_wrapper("Remove Note?", YES_NO_CHOICES);
+    let sure = select_wrapper("Remove Note?", YES_NO_CHOICES)?;
 
     // Gives user an out incase they're filled with a deep regret over
     // their note choice
@@ -299,103 +726,493 @@ fn io_remove_note(note_map: &mut HashMap<String, Note>) -> Result<String, MainEr
         "YES" => {
             io_del_note(name, note_map)
         },
-        _ => Err(MainError::DriverError("No Note was removed".to_string())),
+        _ => Err(MainError::Driver("No Note was removed".to_string())),
     }
     
 }
 
 // Given a .txt or .md files, removes matching names
-fn io_remove_notes_wth_file(note_map: &mut HashMap<String, Note>) -> Result<String, MainError> {
+fn io_remove_notes_wth_file(note_map: &mut NoteMap) -> Result<String, MainError> {
     let file_types = ["Markdown (.md)", "Text (.txt)"];
-    let choice = select_wrapper("Select file type", &file_types);
+    let choice = select_wrapper("Select file type", &file_types)?;
 
-    match file_types[choice] {
+    let names = match file_types[choice] {
         "Markdown (.md)" => {
-            let file_path = io_get_file_path(".md");
-            // Gets the min_hashes for markdown parsing
-            let markdown_choices = ["H1 (#)", "H2 (##)", "H3 (###)", "H4 (####)", "H5 (#####)", "H6 (######)"];
-            let header_length = select_wrapper(
-                format!("Whats the smallest header type you would like to include for file:{}", file_path).as_str(),
-                        &markdown_choices
-            );            
-            let names = get_note_names_from_markdown(file_path.as_str(), header_length)?;
-            let prefix = io_get_prefix();
-            
-            for name in names {
-                let mut note_name = prefix.clone();
-                note_name.push_str(name.as_str());
-                match io_del_note(note_name, note_map) {
-                    Ok(msg) => println!("{}", green_wrap!(msg)),
-                    Err(msg) => println!("{}", red_wrap!(msg)),
-                }
-            }
-            Ok("Any notes found were removed...".to_string())
+            let file_path = io_get_file_path(".md")?;
+            let import = io_read_tree_import(&file_path, BTreeSet::new())?;
+            note_tree_names(&import, note_map)
         },
         "Text (.txt)" => {
-            let file_path = io_get_file_path(".txt");
-
-            match get_note_names_from_file(file_path.as_str()) {
-                Ok(names) => {
-                    let prefix = io_get_prefix();
-                    for name in names {
-                        let mut note_name = prefix.clone();
-                        note_name.push_str(name.as_str());
-                        match io_del_note(note_name, note_map) {
-                            Ok(msg) => println!("{}", green_wrap!(msg)),
-                            Err(msg) => println!("{}", red_wrap!(msg)),
-                        }
-                    }
-                    Ok("Any notes found were removed...".to_string())
-                },
-                Err(e) => Err(e.into()),
-            }
+            let file_path = io_get_file_path(".txt")?;
+            let prefix = io_get_prefix()?;
+            get_note_names_from_file(file_path.as_str())?.into_iter().map(|name| format!("{prefix}{name}")).collect()
         },
-        _ => Ok("This errr...his wasn't an option? How did you...oh..OH MY GOD NO PUT IT DOWN!! SOMEONE HELP, WHY ME NO PLZ PFHDSUDIK...".to_string())
+        _ => return Ok("This errr...his wasn't an option? How did you...oh..OH MY GOD NO PUT IT DOWN!! SOMEONE HELP, WHY ME NO PLZ PFHDSUDIK...".to_string())
+    };
+    io_remove_notes(names, note_map)
+}
+
+// Names of the notes importing the file would add, the file first then its headers named the same way as
+// create_note_tree. Notes imported from the file under another name, e.g. as a header above them was skipped,
+// are found by the header they came from
+fn note_tree_names(import: &TreeImport, note_map: &NoteMap) -> Vec<String> {
+    let TreeImport { path: file_path, headings, prefix, .. } = import;
+    let path = fs::canonicalize(file_path).unwrap_or(file_path.clone());
+    let imported_name = |heading: Option<&String>| note_map.values()
+        .find(|note| note.source.as_ref().is_some_and(|source| source.path == path && source.heading.as_ref() == heading))
+        .map(|note| note.name.clone());
+    let name_for = |name: String, heading: Option<&String>| match note_map.contains_name(&name) {
+        true => name,
+        false => imported_name(heading).unwrap_or(name),
+    };
+
+    let file_name = file_path.file_stem().map_or(String::new(), |stem| stem.to_string_lossy().to_string());
+    let mut names = vec![name_for(format!("{prefix}{file_name}"), None)];
+    // Level, name and text of each header above the current one, the file being level 0
+    let mut above: Vec<(usize, String, String)> = vec![(0, names[0].clone(), file_name)];
+    for heading in headings {
+        while above.last().is_some_and(|(level, _, _)| *level >= heading.level) {
+            above.pop();
+        }
+        let Some((_, parent_name, parent_text)) = above.last().cloned() else {
+            continue;
+        };
+        // Merged into the note above, like a title matching the file
+        if name_key(&heading.name) == name_key(&parent_text) {
+            above.push((heading.level, parent_name, heading.name.clone()));
+            continue;
+        }
+        let name = name_for(scoped_name(prefix, Some(&parent_name), &heading.name), Some(&heading.name));
+        names.push(name.clone());
+        above.push((heading.level, name, heading.name.clone()));
     }
+    names
 }
 
-// Handles the review, getting the notes to review, fomratting their display and upadting 
-// the notes's values.
-fn io_generate_review(note_map: &mut HashMap<String, Note>) -> Result<String, MainError> {
+// Moves the notes with the given names to the trash, once they've been previewed along with
+// any names that don't match a note
+fn io_remove_notes(names: Vec<String>, note_map: &mut NoteMap) -> Result<String, MainError> {
+    let mut found: Vec<(NoteId, String)> = vec![];
+    let mut unmatched: Vec<String> = vec![];
+    for name in names {
+        match note_map.get_by_name(&name) {
+            Some(note) if found.iter().any(|(id, _)| *id == note.id) => (),
+            Some(note) => found.push((note.id, note.name.clone())),
+            None => unmatched.push(name),
+        }
+    }
+
+    let mut preview_map = note_map.clone();
+    for (id, _) in &found {
+        preview_map.remove(id);
+    }
+    let found_names: Vec<String> = found.iter().map(|(_, name)| name.clone()).collect();
+    let unchanged = [("Would be overwritten", overwritten_names(note_map, &preview_map)), ("No note has the name, nothing to remove", unmatched)];
+    let selected = io_preview("Notes to move to the trash", &found_names, &unchanged)?
+        .ok_or(MainError::Driver("Cancelled, no notes were removed".to_string()))?;
+    let mut removed = 0;
+    for ((id, _), _) in found.iter().zip(selected).filter(|(_, keep)| *keep) {
+        if note_map.remove(id).is_some() {
+            removed += 1;
+        }
+    }
+    Ok(format!("{} notes were moved to the trash", removed))
+}
+
+// Handles the review, getting the notes to review from the collections scheduler,
+// fomratting their display and upadting the notes's values.
+fn io_generate_review(note_map: &mut NoteMap, settings: &Settings) -> Result<String, MainError> {
     // Handle case where map is empty
     io_handle_empty_map(note_map)?;
 
-    let (mut uncommon, mut oldest) = get_notes_to_review(note_map);
+    // Only notes matching the tag expression are considered for the review, and never archived ones
+    let filter = io_get_tag_filter()?;
+    // Limited to the notes below one note, once notes have been imported as a tree
+    let notes_to_review = match io_select_subtree(note_map)? {
+        Some(id) => note_map.subtree(&id),
+        None => note_map.clone(),
+    };
+    let scheduler = settings.scheduler.scheduler(settings);
+    let sections = scheduler.get_notes_to_review(&notes_to_review.filtered(|note| !note.archived && filter.matches_note(note)), Local::now());
 
     // Formats and prints Notes to Review \\ 
 
-    format_review(&uncommon, &oldest);
+    format_review(scheduler.name(), &sections);
 
-    // Save Review
-    let save = select_wrapper("Save Review?", YES_NO_CHOICES);
+    let notes: Vec<Note> = sections.into_iter().flat_map(|s| s.notes).collect();
+    if notes.is_empty() {
+        return Err(MainError::Driver("No notes are due for review".to_string()));
+    }
 
-    match YES_NO_CHOICES[save] {
-        "YES" => {
-                // Join together two sets of notes and update them in the json file
-                uncommon.append(&mut oldest);
-                update_reviewed_notes(note_map, uncommon);
-                Ok("Notes Saved".to_string())
+    // Goes through the notes one at a time, getting how well each was recalled.
+    // Skipped notes are left as they were
+    let mut choices = scheduler.grades().to_vec();
+    choices.push("Skip");
+    let mut reviewed: Vec<GradedNote> = vec![];
+    let mut skipped = 0;
+    for note in notes {
+        let started = Instant::now();
+        let grade = select_wrapper(
+            format!("How well did you recall {}?", bold_wrap!(note.name)).as_str(),
+            &choices)?;
+        if choices[grade] == "Skip" {
+            skipped += 1;
+        } else {
+            reviewed.push(GradedNote { note, grade, time_spent: started.elapsed() });
+        }
+    }
+
+    if reviewed.is_empty() {
+        return Err(MainError::Driver("All notes were skipped, nothing was saved".to_string()));
+    }
+    let saved = reviewed.len();
+    match update_reviewed_notes(note_map, settings, reviewed) {
+        0 => Ok(format!("{} Notes Saved, {} Skipped", saved, skipped)),
+        rolled_up => Ok(format!("{} Notes Saved, {} Skipped, {} Parent Notes Rolled Up", saved, skipped, rolled_up)),
+    }
+}
+
+// Lets the user change the collections settings and saves them.
+// Read only notes keep the changes until the tracker is closed, without saving them
+fn io_edit_settings(settings: &mut Settings, read_only: bool) -> Result<String, MainError> {
+    let options = ["Scheduler", "FSRS Target Retention", "Leitner Box Cadence", "Backup Count", "Trash Days", "Roll Up Reviews", "Save"];
+    loop {
+        match options[select_wrapper("What would you like to change?", &options)?] {
+            "Scheduler" => {
+                let choice = select_wrapper(
+                    format!("Select the review scheduler\nCurrent: {}", bold_wrap!(settings.scheduler)).as_str(),
+                    SchedulerKind::ALL)?;
+                settings.scheduler = SchedulerKind::ALL[choice];
+                println!("{}", green_wrap!(format!("Reviews will now use {}", bold_wrap!(settings.scheduler))));
+            },
+            "FSRS Target Retention" => {
+                // Unwrap will always pass due to validator
+                settings.fsrs_retention = Input::with_theme(&ColorfulTheme::default())
+                    .with_prompt(format!("Enter the target retention %\nCurrent: {:.0}", settings.fsrs_retention * 100.0))
+                    .validate_with(|input: &String| -> Result<(), &str> {
+                        match input.parse::<f32>() {
+                            Ok(p) if (50.0..=99.0).contains(&p) => Ok(()),
+                            _ => Err("Must enter a number between 50 and 99"),
+                        }
+                    })
+                    .interact()?.parse::<f32>().unwrap() / 100.0;
+                println!("{}", green_wrap!(format!("Target retention set to {:.0}%", settings.fsrs_retention * 100.0)));
+            },
+            "Leitner Box Cadence" => {
+                // Unwrap will always pass due to validator
+                settings.leitner_cadence = Input::<String>::with_theme(&ColorfulTheme::default())
+                    .with_prompt(format!("Enter the days between reviews for each box, seperated by commas\nCurrent: {}",
+                        settings.leitner_cadence.iter().join(", ")))
+                    .validate_with(|input: &String| -> Result<(), &str> {
+                        match parse_cadence(input) {
+                            Some(_) => Ok(()),
+                            None => Err("Must enter positive numbers, e.g. 1, 2, 4, 8, 16"),
+                        }
+                    })
+                    .interact()
+                    .map(|input| parse_cadence(&input).unwrap())?;
+                println!("{}", green_wrap!(format!("Leitner boxes set to {}", bold_wrap!(settings.leitner_cadence.iter().join(", ")))));
+            },
+            "Backup Count" => {
+                // Unwrap will always pass due to validator
+                settings.backup_count = Input::with_theme(&ColorfulTheme::default())
+                    .with_prompt(format!("Enter the number of backups to keep, 0 turns them off\nCurrent: {}", settings.backup_count))
+                    .validate_with(|input: &String| -> Result<(), &str> {
+                        match input.parse::<usize>() {
+                            Ok(_) => Ok(()),
+                            Err(_) => Err("Must enter a positive number"),
+                        }
+                    })
+                    .interact()?.parse::<usize>().unwrap();
+                println!("{}", green_wrap!(format!("Keeping {} backups", bold_wrap!(settings.backup_count))));
             },
-            _ => Err(MainError::DriverError("Notes were not saved".to_string()))
+            "Trash Days" => {
+                // Unwrap will always pass due to validator
+                settings.trash_days = Input::with_theme(&ColorfulTheme::default())
+                    .with_prompt(format!("Enter the days removed notes stay in the trash, 0 keeps them forever\nCurrent: {}", settings.trash_days))
+                    .validate_with(|input: &String| -> Result<(), &str> {
+                        match input.parse::<u32>() {
+                            Ok(_) => Ok(()),
+                            Err(_) => Err("Must enter a positive number"),
+                        }
+                    })
+                    .interact()?.parse::<u32>().unwrap();
+                println!("{}", green_wrap!(format!("Keeping trashed notes for {} days", bold_wrap!(settings.trash_days))));
+            },
+            "Roll Up Reviews" => {
+                let choice = select_wrapper(
+                    format!("Should reviewing a note also review the note it sits under?\nCurrent: {}",
+                        bold_wrap!(if settings.roll_up_reviews { "YES" } else { "NO" })).as_str(),
+                    YES_NO_CHOICES)?;
+                settings.roll_up_reviews = YES_NO_CHOICES[choice] == "YES";
+                println!("{}", green_wrap!(match settings.roll_up_reviews {
+                    true => "Reviews will roll up to parent notes",
+                    false => "Reviews will no longer roll up to parent notes",
+                }));
+            },
+            _ => {
+                if read_only {
+                    return Err(MainError::Driver(
+                        "The notes are open read only, so the settings weren't saved and only last until the tracker is closed".to_string()));
+                }
+                save_settings(settings)?;
+                return Ok("Settings Saved".to_string());
+            }
         }
+    }
+}
+
+
+// Lets the user create, switch, rename and delete profiles.
+// Switching swaps the map and settings for the chosen profiles
+fn io_profiles(note_map: &mut NoteMap, map_store: &mut MapStore, settings: &mut Settings) -> Result<String, MainError> {
+    let profiles = list_profiles()?;
+    // Profile whose notes are currently open, None if opened with --store or a custom path
+    let current = profiles.iter()
+        .find(|name| get_profile_store_path(name).is_ok_and(|p| p == get_store_path()))
+        .cloned();
+    println!("Current profile: {}", bold_wrap!(current.clone().unwrap_or("None (custom notes file)".to_string())));
+
+    let options = ["Switch", "Create", "Rename", "Delete", "Back"];
+    match options[select_wrapper("Profiles", &options)?] {
+        "Switch" => {
+            let name = &profiles[select_wrapper("Select a profile", &profiles)?];
+            // Save the current notes before moving to the other profile
+            map_store.flush(note_map, "Unsaved changes")?;
+            let previous = get_store_path();
+            set_store_path(get_profile_store_path(name)?);
+            match map_store.reopen() {
+                Ok(map) => *note_map = map,
+                // Most likely open in another tracker, so stay on the current profile
+                Err(e) => {
+                    set_store_path(previous);
+                    *note_map = map_store.reopen()?;
+                    return Err(e.into());
+                },
+            }
+            *settings = load_settings()?;
+            io_create_backup(map_store, settings);
+            io_purge_old_trash(map_store, settings);
+            if map_store.is_read_only() {
+                return Ok(format!("Switched to profile {} until the tracker is closed
+The notes are open read only, so it wasn't saved as the active profile",
+                    bold_wrap!(name)));
+            }
+            set_active_profile(name)?;
+            Ok(format!("Switched to profile {}", bold_wrap!(name)))
+        },
+        "Create" => {
+            let name = input_wrapper("Enter the new profiles name")?;
+            create_profile(&name)?;
+            Ok(format!("Created profile {}, switch to it to start adding notes", bold_wrap!(name)))
+        },
+        "Rename" => {
+            let name = &profiles[select_wrapper("Select a profile to rename", &profiles)?];
+            let new_name = input_wrapper("Enter the new name")?;
+            map_store.flush(note_map, "Unsaved changes")?;
+            rename_profile(name, &new_name)?;
+            // Follow the open profile to its new folder
+            if current.as_ref() == Some(name) {
+                set_store_path(get_profile_store_path(&new_name)?);
+                *note_map = map_store.reopen()?;
+            }
+            Ok(format!("Renamed profile {} to {}", bold_wrap!(name), bold_wrap!(new_name)))
+        },
+        "Delete" => {
+            let name = &profiles[select_wrapper("Select a profile to delete", &profiles)?];
+            if current.as_ref() == Some(name) {
+                return Err(MainError::Driver("Can't delete the open profile, switch to another first".to_string()));
+            }
+            let sure = select_wrapper(
+                format!("Delete profile {} and all of its notes?", bold_wrap!(name)).as_str(),
+                YES_NO_CHOICES)?;
+            match YES_NO_CHOICES[sure] {
+                "YES" => {
+                    delete_profile(name)?;
+                    Ok(format!("Deleted profile {}", bold_wrap!(name)))
+                },
+                _ => Err(MainError::Driver("No profile was deleted".to_string())),
+            }
+        },
+        _ => Ok("".to_string()),
+    }
+}
+
+// Backs up the current notes file, warning rather than stopping if it can't
+fn io_create_backup(map_store: &MapStore, settings: &Settings) {
+    // Backing up also deletes the oldest backups, which read only notes shouldn't do
+    if map_store.is_read_only() {
+        println!("{} The notes are open read only, so no backup was made", red_wrap!("Note:"));
+        return;
+    }
+    if let Err(e) = backup::create_backup(settings.backup_count) {
+        println!("{} Could not back up notes due to error {}", red_wrap!("Warning:"), e);
+    }
+}
+
+// Lists the saved backups and lets the user restore one,
+// replacing the map with the restored notes
+fn io_backups(note_map: &mut NoteMap, map_store: &mut MapStore, settings: &Settings) -> Result<String, MainError> {
+    let backups = backup::list_backups()?;
+    if backups.is_empty() {
+        return Err(MainError::Driver(format!("No backups found in {}", bold_wrap!(backup::get_backup_dir().display()))));
+    }
+    let mut choices = backups.iter()
+        .map(|b| b.time.format("%Y-%m-%d %H:%M:%S").to_string())
+        .collect_vec();
+    choices.push("Back".to_string());
+    let choice = select_wrapper(
+        format!("Backups in {}\nSelect one to restore", bold_wrap!(backup::get_backup_dir().display())).as_str(),
+        &choices)?;
+    if choice == backups.len() {
+        return Ok("".to_string());
+    }
+
+    let sure = select_wrapper(
+        format!("Replace the current notes with the backup from {}?", bold_wrap!(choices[choice])).as_str(),
+        YES_NO_CHOICES)?;
+    match YES_NO_CHOICES[sure] {
+        "YES" => {
+            if map_store.is_read_only() {
+                return Err(TrackerError::ReadOnly.into());
+            }
+            // Save the current map first so it's included in the backup taken before restoring
+            map_store.flush(note_map, "Unsaved changes")?;
+            backup::restore_backup(&backups[choice], settings.backup_count)?;
+            *note_map = map_store.reopen()?;
+            // The journal describes the notes from before the restore, so it no longer applies
+            map_store.clear_journal()?;
+            Ok(format!("Restored notes from {}, undo history was cleared", bold_wrap!(choices[choice])))
+        },
+        _ => Err(MainError::Driver("No backup was restored".to_string())),
+    }
 }
 
+// Lets the user look through, restore and purge the notes in the trash
+fn io_trash(note_map: &mut NoteMap, map_store: &mut MapStore) -> Result<String, MainError> {
+    if map_store.trashed().is_empty() {
+        return Err(MainError::Driver("The trash is empty".to_string()));
+    }
+    println!("{}\n", format_trash(map_store.trashed()));
+    let choices = map_store.trashed().iter().map(format_trashed).collect_vec();
+
+    let options = ["Restore", "Purge", "Purge All", "Back"];
+    match options[select_wrapper("Trash", &options)?] {
+        "Restore" => {
+            let index = select_wrapper("Select a note to restore", &choices)?;
+            let name = match map_store.restore_trashed(note_map, index, None) {
+                Err(TrackerError::NameConflict(name)) => {
+                    // Give the user the chance to restore it under another name
+                    println!("{}", red_wrap!(TrackerError::NameConflict(name)));
+                    let new_name = input_wrapper("Enter a new name to restore it as")?;
+                    map_store.restore_trashed(note_map, index, Some(new_name))?
+                },
+                restored => restored?,
+            };
+            Ok(format!("Restored {} from the trash", bold_wrap!(name)))
+        },
+        "Purge" => {
+            let index = select_wrapper("Select a note to permanently delete", &choices)?;
+            io_confirm_purge(map_store, &[index])
+        },
+        "Purge All" => {
+            let indices = (0..choices.len()).collect_vec();
+            io_confirm_purge(map_store, &indices)
+        },
+        _ => Ok("".to_string()),
+    }
+}
+
+// Asks before permanently deleting trashed notes
+fn io_confirm_purge(map_store: &mut MapStore, indices: &[usize]) -> Result<String, MainError> {
+    let sure = select_wrapper(
+        format!("Permanently delete {} notes? This can't be undone", indices.len()).as_str(),
+        YES_NO_CHOICES)?;
+    match YES_NO_CHOICES[sure] {
+        "YES" => Ok(format!("Permanently deleted {} notes from the trash", map_store.purge_trashed(indices)?)),
+        _ => Err(MainError::Driver("Nothing was purged".to_string())),
+    }
+}
+
+// Purges notes that have been in the trash longer than the settings allow
+fn io_purge_old_trash(map_store: &mut MapStore, settings: &Settings) {
+    match map_store.purge_old_trash(settings.trash_days) {
+        Ok(0) => {},
+        Ok(purged) => println!("Purged {} notes that were in the trash for over {} days", purged, settings.trash_days),
+        Err(e) => println!("{} Could not purge old notes from the trash due to error {}", red_wrap!("Warning:"), e),
+    }
+}
+
+// Formats a trashed note for lists
+fn format_trashed(trashed: &trash::TrashedNote) -> String {
+    format!("{} - removed {}, reviewed {} times",
+        trashed.note.name, format_time_for_output(&trashed.removed), trashed.note.freq)
+}
+
+// Lists every note in the trash, oldest first
+fn format_trash(trashed: &[trash::TrashedNote]) -> String {
+    match trashed.is_empty() {
+        true => "The trash is empty".to_string(),
+        false => format!("{}\n{}", bold_wrap!("...Trash..."), trashed.iter().map(format_trashed).join("\n")),
+    }
+}
+
+// Undoes or redoes one step, showing what can be undone and redone first
+fn io_undo(note_map: &mut NoteMap, map_store: &mut MapStore, undo: bool) -> Result<String, MainError> {
+    println!("{}\n", format_history(map_store));
+    io_step(note_map, map_store, undo)
+}
+
+// Undoes or redoes the most recent step
+fn io_step(note_map: &mut NoteMap, map_store: &mut MapStore, undo: bool) -> Result<String, MainError> {
+    let (verb, operation) = match undo {
+        true => ("Undid", map_store.undo(note_map)?),
+        false => ("Redid", map_store.redo(note_map)?),
+    };
+    match operation {
+        Some(operation) => Ok(format!("{verb} {} from {}, changing {} notes",
+            bold_wrap!(operation.action),
+            format_time_for_output(&operation.time),
+            operation.changes.len())),
+        None => Err(MainError::Driver(format!("Nothing to {}", if undo { "undo" } else { "redo" }))),
+    }
+}
+
+// Lists the steps that can be undone and redone, most recent first
+fn format_history(map_store: &MapStore) -> String {
+    let (undo, redo) = map_store.history();
+    let format_steps = |steps: Vec<&journal::Operation>| match steps.is_empty() {
+        true => "\tNothing".to_string(),
+        false => steps.iter()
+            .map(|op| format!("\t{} - {} ({} notes)", format_time_for_output(&op.time), op.action, op.changes.len()))
+            .join("\n"),
+    };
+    format!("{}\n{}\n{}\n{}", bold_wrap!("Can undo:"), format_steps(undo), bold_wrap!("Can redo:"), format_steps(redo))
+}
+
+// Parses comma seperated days for the leitner boxes, None if any aren't positive numbers
+fn parse_cadence(input: &str) -> Option<Vec<u32>> {
+    let cadence = input.split(',')
+        .map(|days| days.trim().parse::<u32>().ok().filter(|d| *d > 0))
+        .collect::<Option<Vec<u32>>>()?;
+    (!cadence.is_empty()).then_some(cadence)
+}
 
 // Handle error handling wheen map is empty
-fn io_handle_empty_map(note_map: &HashMap<String, Note>) -> Result<String, MainError> {
+fn io_handle_empty_map(note_map: &NoteMap) -> Result<String, MainError> {
     if !note_map.is_empty() {
         Ok("".to_string())
     } else {
-        Err(MainError::DriverError(format!("{}{}",
+        Err(MainError::Driver(format!("{}{}",
         green_wrap!("No notes to review\nTry adding some notes with "),
         bold_wrap!("Add Note")).to_string()))
     }
 }
 
 // Gets and validates files path accoring to given types
-fn io_get_file_path(file_type: &str) -> String{
+fn io_get_file_path(file_type: &str) -> Result<String, MainError> {
     // Gets file path
-    Input::new()
+    Ok(Input::new()
     .with_prompt("Enter the file path")
     .validate_with(|input: &String| -> Result<(), &str> {
         if input.chars().count() < file_type.len() {
@@ -411,118 +1228,812 @@ fn io_get_file_path(file_type: &str) -> String{
             Ok(())
         }
     })
-    .interact()
-    .unwrap()
+    .interact()?)
 }
 
 // Given a vector of strings and a prefix, inserts the prefix to each name and creates a new note with
-// said name into the map
-fn io_create_new_notes_from_vec(prefix: String, note_names: Vec<String>, note_map: &mut HashMap<String, Note>) {
+// said name and tags into the map, once they've been previewed. Names already in the map are left as they are.
+// Returns how many notes were added, None if cancelled
+fn io_create_new_notes_from_vec(prefix: String, note_names: Vec<String>, tags: &BTreeSet<String>, note_map: &mut NoteMap) -> Result<Option<usize>, MainError> {
+    let mut new_notes: Vec<Note> = vec![];
+    let mut existing: Vec<String> = vec![];
+    // Keys of the names to add, so a name repeated in the file is only added once
+    let mut seen: HashSet<String> = HashSet::new();
     for name in note_names {
-        let mut note_name: String = prefix.clone();
-        note_name.push_str(name.as_str());
-        note_map.insert(note_name.clone(), Note::new(note_name, 0, Local::now().to_string()));
-    }            
+        let Ok(name) = clean_name(&format!("{prefix}{name}")) else {
+            continue;
+        };
+        if note_map.contains_name(&name) || !seen.insert(name_key(&name)) {
+            existing.push(name);
+            continue;
+        }
+        let mut note = Note::new(name, 0, Local::now());
+        note.tags = tags.clone();
+        new_notes.push(note);
+    }
+
+    let mut preview_map = note_map.clone();
+    for note in &new_notes {
+        // Names already in the map were left out above
+        let _ = preview_map.insert(note.clone());
+    }
+    let names: Vec<String> = new_notes.iter().map(|note| note.name.clone()).collect();
+    let unchanged = [("Would be overwritten", overwritten_names(note_map, &preview_map)), ("Already exist, kept as they are", existing)];
+    let Some(selected) = io_preview("Notes to add", &names, &unchanged)? else {
+        return Ok(None);
+    };
+    let mut added = 0;
+    for (note, _) in new_notes.into_iter().zip(selected).filter(|(_, keep)| *keep) {
+        if note_map.insert(note).is_ok() {
+            added += 1;
+        }
+    }
+    Ok(Some(added))
 }
 
-// Get notes from header names in a markdown file, according to the given header level, and adds it to the map
-fn io_get_notes_from_markdown(file_path: String, note_map: &mut HashMap<String, Note>) -> Result<String, MainError>{
-    let markdown_choices = ["H1 (#)", "H2 (##)", "H3 (###)", "H4 (####)", "H5 (#####)", "H6 (######)"];
-    // Gets the min_hashes for markdown parsing
-    let header_length = select_wrapper(
-        format!("Whats the smallest header type you would like to include for file:{}", file_path).as_str(),
-    &markdown_choices
-    );
-    // Gets header names
-    match get_note_names_from_markdown(file_path.as_str(), header_length+1) {
-        Ok(note_names) => {
-            let prefix = io_get_prefix();
-            io_create_new_notes_from_vec(prefix, note_names, note_map);      
-            Ok(format!("New Notes successfully added from file {}", bold_wrap!(file_path)))
+// Reads the header names in a markdown file, according to the header level the user picks, to import them
+// as a tree under a note for the file.
+// The notes are tagged with the given tags, along with the file name and any front matter tags
+fn io_read_tree_import(file_path: &str, mut tags: BTreeSet<String>) -> Result<TreeImport, MainError> {
+    let header_level = io_get_header_level(file_path)?;
+    let headings = get_headings_from_markdown(file_path, header_level)
+        .map_err(|e| MainError::Driver(format!("Could not get names, due to error: {e}")))?;
+    let prefix = io_get_prefix()?;
+    tags.extend(file_name_tag(file_path));
+    tags.extend(front_matter::get_front_matter_tags(Path::new(file_path))?
+        .iter().filter_map(|tag| tags::clean_tag(tag)));
+    Ok(TreeImport { path: PathBuf::from(file_path), headings, prefix, tags })
+}
+
+// A markdown file to import as a tree of notes, read before anything is added so it can be previewed
+struct TreeImport {
+    path: PathBuf,
+    headings: Vec<Heading>,
+    prefix: String,
+    tags: BTreeSet<String>,
+}
+
+// Adds the notes for each file once they've been previewed, leaving out any the user unticks.
+// They're added to a copy of the map first, to find which would be new. Unticking one can change
+// the rest, e.g. the headers below it move up and are named after the note above instead, so the
+// import is worked out again and previewed until every change listed is made.
+// Returns the notes used for each file in order, None if cancelled
+fn io_import_trees(imports: &[TreeImport], note_map: &mut NoteMap) -> Result<Option<Vec<NoteTree>>, MainError> {
+    // File and place in it of each note the user unticked
+    let mut unticked: HashSet<(usize, usize)> = HashSet::new();
+    loop {
+        let mut preview_map = note_map.clone();
+        // File and place in it of each note that would be added, with how it's listed
+        let mut changes: Vec<((usize, usize), String)> = vec![];
+        let mut existing: Vec<String> = vec![];
+        for (index, import) in imports.iter().enumerate() {
+            let tree = create_note_tree(import, &skipped_places(&unticked, index), &mut preview_map)?;
+            let file_name = import.path.file_name().map_or(String::new(), |name| name.to_string_lossy().to_string());
+            // Headers merged into the note above them have its id, so each note is only listed where it was added
+            let mut listed: HashSet<NoteId> = HashSet::new();
+            for (position, id) in iter::once(tree.file).chain(tree.headings.iter().copied()).enumerate() {
+                let id = id.filter(|id| tree.added.contains(id) && listed.insert(*id));
+                if let Some(note) = id.and_then(|id| preview_map.get(&id)) {
+                    changes.push(((index, position), format!("{} ({})", note.name, file_name)));
+                }
+            }
+            existing.extend(tree.kept);
+        }
+
+        let names: Vec<String> = changes.iter().map(|(_, name)| name.clone()).collect();
+        let unchanged = [("Would be overwritten", overwritten_names(note_map, &preview_map)), ("Already exist, kept as they are", existing)];
+        let Some(selected) = io_preview("Notes to add", &names, &unchanged)? else {
+            return Ok(None);
+        };
+        let newly_unticked: Vec<(usize, usize)> = changes.iter().zip(selected)
+            .filter(|(_, keep)| !keep)
+            .map(|((place, _), _)| *place)
+            .collect();
+        if newly_unticked.is_empty() {
+            break;
+        }
+        unticked.extend(newly_unticked);
+    }
+
+    let mut trees: Vec<NoteTree> = vec![];
+    for (index, import) in imports.iter().enumerate() {
+        trees.push(create_note_tree(import, &skipped_places(&unticked, index), note_map)?);
+    }
+    Ok(Some(trees))
+}
+
+// Places in the file with the given index that were unticked
+fn skipped_places(unticked: &HashSet<(usize, usize)>, index: usize) -> HashSet<usize> {
+    unticked.iter().filter(|(i, _)| *i == index).map(|(_, position)| *position).collect()
+}
+
+// Adds a note for the file and one for each header, each header sitting under the closest header
+// above it with fewer hashes, and the top headers under the file.
+// Headers are named after the note they sit under, so the same header in two files gets two notes.
+// Notes already in the map are kept as they are. If one is in the same place in the tree, e.g. from importing
+// the file before, the new headers below it are added under it.
+// Places in the skip set, 0 being the file and 1 the first header, aren't added and the notes below go up a level.
+// Added notes remember the header they came from, so the directory can be synced later
+fn create_note_tree(import: &TreeImport, skip: &HashSet<usize>, note_map: &mut NoteMap) -> Result<NoteTree, MainError> {
+    let TreeImport { path: file_path, headings, prefix, tags } = import;
+    let mut added: Vec<NoteId> = vec![];
+    let mut kept: Vec<String> = vec![];
+    let path = fs::canonicalize(file_path).unwrap_or(file_path.clone());
+    let file_name = file_path.file_stem().map_or(String::new(), |stem| stem.to_string_lossy().to_string());
+    let mut file_note = Note::new(format!("{prefix}{file_name}"), 0, Local::now());
+    file_note.tags = tags.clone();
+    file_note.source = Some(NoteSource { path: path.clone(), heading: None, position: 0 });
+    let file_id = match note_map.get_by_name(&file_note.name) {
+        Some(existing) => {
+            kept.push(existing.name.clone());
+            Some(existing.id)
+        },
+        None if skip.contains(&0) => None,
+        None => {
+            let id = note_map.insert(file_note)?;
+            added.push(id);
+            Some(id)
         },
-        Err(e) => Err(MainError::DriverError(format!(
-            "Could not get names, due to error: {e}"))),
+    };
+
+    // Level, id and text of each header above the current one, the file being level 0.
+    // None for headers that were skipped
+    let mut above: Vec<(usize, Option<NoteId>, String)> = vec![(0, file_id, file_name)];
+    let mut heading_ids: Vec<Option<NoteId>> = vec![];
+    for (index, heading) in headings.iter().enumerate() {
+        while above.last().is_some_and(|(level, _, _)| *level >= heading.level) {
+            above.pop();
+        }
+        let (parent, parent_text) = above.iter().rev()
+            .find_map(|(_, id, text)| id.map(|id| (Some(id), name_key(text))))
+            .unwrap_or_default();
+        let parent_name = parent.and_then(|id| note_map.get(&id)).map(|note| note.name.clone());
+        let mut note = Note::new(scoped_name(prefix, parent_name.as_deref(), &heading.name), 0, Local::now());
+        note.tags = tags.clone();
+        note.parent = parent;
+        note.source = Some(NoteSource { path: path.clone(), heading: Some(heading.name.clone()), position: index + 1 });
+        let id = match skip.contains(&(index + 1)) {
+            true => None,
+            // A header named the same as the note it sits under, e.g. a title matching the file, is merged into it
+            false if parent.is_some() && name_key(&heading.name) == parent_text => parent,
+            false => match note_map.insert(note) {
+                Ok(id) => {
+                    added.push(id);
+                    Some(id)
+                },
+                Err(TrackerError::NameConflict(name)) => {
+                    let id = note_map.get_by_name(&name).filter(|n| n.parent == parent).map(|n| n.id);
+                    kept.push(name);
+                    id
+                },
+                // Header names are never empty, so this isn't expected
+                Err(_) => None,
+            },
+        };
+        above.push((heading.level, id, heading.name.clone()));
+        heading_ids.push(id);
+    }
+    Ok(NoteTree { file: file_id, headings: heading_ids, added, kept })
+}
+
+// Notes used for a file and its headers by create_note_tree
+struct NoteTree {
+    // None if it was skipped
+    file: Option<NoteId>,
+    // Note for each header in order, None if it was skipped
+    headings: Vec<Option<NoteId>>,
+    // Notes that were added, rather than already being in the map
+    added: Vec<NoteId>,
+    // Names of notes that were already in the map, kept as they are
+    kept: Vec<String>,
+}
+
+// Lets the user pick a note to limit the review to it and the notes below it.
+// None for every note, which is picked without asking when no notes are in a tree
+fn io_select_subtree(note_map: &NoteMap) -> Result<Option<NoteId>, MainError> {
+    if note_map.values().all(|note| note.parent.is_none()) {
+        return Ok(None);
+    }
+    let tree = note_map.tree();
+    let mut choices = vec!["All notes".to_string()];
+    choices.extend(tree.iter().map(|(depth, note)| format!("{}{}", "    ".repeat(*depth), note.name)));
+    match select_wrapper("Review the notes below", &choices)? {
+        0 => Ok(None),
+        choice => Ok(Some(tree[choice - 1].1.id)),
+    }
+}
+
+// Finds the options to sync each directory with, saving them for next time. Directories synced before keep the
+// header level and prefix they were synced with, as others would add notes that don't match the ones already there,
+// so flags that differ are refused. Otherwise the flags are used, falling back to the defaults
+fn resolve_watch_options(dirs: &[PathBuf], level: Option<usize>, prefix: Option<String>, tags: Option<BTreeSet<String>>) -> Result<BTreeMap<PathBuf, SyncOptions>, MainError> {
+    let mut settings = load_settings()?;
+    let mut roots: BTreeMap<PathBuf, SyncOptions> = BTreeMap::new();
+    for dir in dirs {
+        let root = fs::canonicalize(dir).ok().filter(|root| root.is_dir())
+            .ok_or(MainError::Driver(format!("Could not find the directory {}", bold_wrap!(dir.display()))))?;
+        let options = match settings.synced_dirs.get(&root) {
+            Some(synced) => {
+                if level.is_some_and(|level| level != synced.header_level) || prefix.as_ref().is_some_and(|prefix| *prefix != synced.prefix) {
+                    return Err(MainError::Driver(format!(
+                        "{} was synced with header level {} and prefix \"{}\", watch it with the same ones or sync it from the tracker to change them",
+                        bold_wrap!(root.display()), synced.header_level, synced.prefix)));
+                }
+                SyncOptions { tags: tags.clone().unwrap_or(synced.tags.clone()), ..synced.clone() }
+            },
+            None => SyncOptions {
+                header_level: level.unwrap_or(6),
+                prefix: prefix.clone().unwrap_or_default(),
+                tags: tags.clone().unwrap_or_default(),
+            },
+        };
+        roots.insert(root, options);
+    }
+
+    let unsaved = roots.iter().any(|(root, options)| settings.synced_dirs.get(root) != Some(options));
+    if unsaved {
+        settings.synced_dirs.extend(roots.clone());
+        save_settings(&settings)?;
+    }
+    Ok(roots)
+}
+
+// Watches markdown directories, syncing the notes imported from them whenever their files change.
+// Changes are left to settle for the debounce time first, so saving a file or checking out a branch syncs once.
+// The notes are only opened while syncing so the tracker can still be used, and syncs wait while it's open
+fn watch_directories(roots: &BTreeMap<PathBuf, SyncOptions>, archive: bool, debounce: Duration) -> Result<String, MainError> {
+    let (sender, receiver) = mpsc::channel();
+    let mut debouncer = new_debouncer(debounce, sender)
+        .map_err(|e| MainError::Driver(format!("Could not start watching due to error {e}")))?;
+    for root in roots.keys() {
+        debouncer.watcher().watch(root, RecursiveMode::Recursive)
+            .map_err(|e| MainError::Driver(format!("Could not watch {} due to error {e}", bold_wrap!(root.display()))))?;
+    }
+
+    // Ctrl-c only asks the watch to stop, so a sync is never stopped half way and changes still waiting are synced first
+    let interrupted = ctrlc::set_handler(|| STOP_WATCHING.store(true, Ordering::SeqCst));
+    if let Err(e) = interrupted {
+        println!("{} Could not listen for ctrl-c due to error {}", red_wrap!("Warning:"), e);
+    }
+    log_watch(&format!("Watching {}, press ctrl-c to stop", roots.keys().map(|root| root.display()).join(", ")));
+
+    // Directories with changes still to sync, all of them to begin with
+    let mut pending: BTreeSet<PathBuf> = roots.keys().cloned().collect();
+    // When the notes were last found open in another tracker, None if they weren't
+    let mut waiting: Option<Instant> = None;
+    // Notes whose headers have gone that have been logged, so they're only logged once
+    let mut logged_removed: HashSet<NoteId> = HashSet::new();
+    loop {
+        let stopping = STOP_WATCHING.load(Ordering::SeqCst);
+        // While waiting on another tracker, tries again every few seconds
+        let retry = waiting.is_none_or(|since| since.elapsed() >= WATCH_RETRY);
+        if !pending.is_empty() && (retry || stopping) {
+            match watch_sync(&pending, roots, archive, &mut logged_removed) {
+                Ok(()) => {
+                    pending.clear();
+                    waiting = None;
+                },
+                Err(MainError::Tracker(TrackerError::StorageErr(e @ StorageError::Locked { .. }))) => {
+                    if waiting.is_none() {
+                        log_watch(&format!("{e}, syncing once it's closed"));
+                    }
+                    waiting = Some(Instant::now());
+                },
+                Err(e) => {
+                    log_watch(&red_wrap!(format!("Could not sync due to error {e}")));
+                    pending.clear();
+                },
+            }
+        }
+
+        if stopping {
+            break;
+        }
+
+        // Wakes up now and then to check whether it's been asked to stop
+        match receiver.recv_timeout(WATCH_TICK) {
+            Ok(Ok(events)) => pending.extend(events.iter()
+                .filter_map(|event| roots.keys().find(|root| is_note_change(root, &event.path)))
+                .cloned()),
+            Ok(Err(e)) => log_watch(&red_wrap!(format!("Watching error {e}"))),
+            Err(mpsc::RecvTimeoutError::Timeout) => (),
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+    }
+    match pending.is_empty() {
+        true => Ok("\nStopped watching".to_string()),
+        false => Err(MainError::Driver(format!("\nStopped watching, changes to {} weren't synced as the notes are open in another tracker",
+            pending.iter().map(|root| root.display()).join(", ")))),
+    }
+}
+
+// Syncs each of the given directories, saving and logging any changes
+fn watch_sync(pending: &BTreeSet<PathBuf>, roots: &BTreeMap<PathBuf, SyncOptions>, archive: bool, logged_removed: &mut HashSet<NoteId>) -> Result<(), MainError> {
+    let (mut map_store, mut note_map) = MapStore::open(false)?;
+    for (root, options) in roots.iter().filter(|(root, _)| pending.contains(*root)) {
+        let files = read_sync_files(root, options.header_level, &options.tags);
+        let plan = sync::plan_sync(&note_map, root, &files);
+        let report = sync::apply_sync(&mut note_map, &plan, &files, &options.prefix);
+        if archive {
+            sync::archive(&mut note_map, &plan.removed);
+        }
+        let written = map_store.flush(&mut note_map, &format!("Watch {}", root.display()))?;
+        let newly_removed: Vec<NoteId> = plan.removed.iter().copied().filter(|id| logged_removed.insert(*id)).collect();
+        if written == 0 && newly_removed.is_empty() {
+            continue;
+        }
+        log_watch(&format!("Synced {}", bold_wrap!(root.display())));
+        print_sync_changes(&report);
+        if !newly_removed.is_empty() {
+            print_removed_headers(&note_map, &newly_removed);
+        }
+        println!("{}", green_wrap!(format_sync(&plan, &report, files.len(), archive)));
+    }
+    Ok(())
+}
+
+// Whether a change could change the notes, being to a markdown file or a folder,
+// outside of hidden folders like .git
+fn is_note_change(root: &Path, path: &Path) -> bool {
+    path.strip_prefix(root).is_ok_and(|relative| !relative.iter().any(|part| part.to_string_lossy().starts_with('.')))
+        && path.extension().is_none_or(|extension| extension == "md")
+}
+
+// Prints a line of the watch log, starting with the time
+fn log_watch(message: &str) {
+    println!("[{}] {}", format_time_for_output(&Local::now()), message);
+}
+
+// Syncs the notes imported from a markdown directory with it. New headers are added, renamed and
+// moved headers keep their notes and history, and notes whose header has gone can be archived
+fn io_sync_directory(note_map: &mut NoteMap, settings: &mut Settings, read_only: bool) -> Result<String, MainError> {
+    let root = fs::canonicalize(io_get_file_path("")?)
+        .ok().filter(|root| root.is_dir())
+        .ok_or(MainError::Driver("Could not Find directory".to_string()))?;
+    let header_level = io_get_header_level(&root.to_string_lossy())?;
+    let prefix = io_get_prefix()?;
+    let tags = io_get_tags("Enter tags for new notes, seperated by spaces (optional)\nFile name, folder and front matter tags are added too", &BTreeSet::new())?;
+
+    let files = read_sync_files(&root, header_level, &tags);
+    if files.is_empty() {
+        return Err(MainError::Driver("No markdown files were found in the directory".to_string()));
+    }
+
+    let mut plan = sync::plan_sync(note_map, &root, &files);
+    if !io_preview_sync(&mut plan, &files, &prefix, note_map)? {
+        return Err(MainError::Driver("Cancelled, nothing was synced".to_string()));
+    }
+    let mut archive = false;
+    if !plan.removed.is_empty() {
+        print_removed_headers(note_map, &plan.removed);
+        archive = YES_NO_CHOICES[select_wrapper(
+            "Archive these notes? They keep their history but are left out of reviews",
+            YES_NO_CHOICES)?] == "YES";
+    }
+
+    let report = sync::apply_sync(note_map, &plan, &files, &prefix);
+    if archive {
+        sync::archive(note_map, &plan.removed);
+    }
+    // Watching the directory syncs it the same way
+    settings.synced_dirs.insert(root, SyncOptions { header_level, prefix, tags });
+    if read_only {
+        println!("{} The notes are open read only, so the sync options weren't saved", red_wrap!("Warning:"));
+    } else if let Err(e) = save_settings(settings) {
+        println!("{} The sync options weren't saved due to error {}", red_wrap!("Warning:"), e);
+    }
+    Ok(format_sync(&plan, &report, files.len(), archive))
+}
+
+// Previews the notes syncing would add and rename, by syncing a copy of the map.
+// Unticked headers are left out of the plan. Unticking a renamed header adds it as a new note
+// rather than renaming the old one, other unticked renames keep their old name.
+// Returns false if cancelled
+fn io_preview_sync(plan: &mut sync::SyncPlan, files: &[sync::SyncFile], prefix: &str, note_map: &NoteMap) -> Result<bool, MainError> {
+    let mut preview_map = note_map.clone();
+    let report = sync::apply_sync(&mut preview_map, plan, files, prefix);
+    let added: Vec<&Note> = preview_map.values()
+        .filter(|note| !note_map.contains(&note.id))
+        .sorted_by_key(|note| note.source.as_ref().map(|source| (source.path.clone(), source.position)))
+        .collect();
+    let renamed: Vec<(NoteId, &(String, String))> = report.renamed.iter()
+        .filter_map(|names| note_map.id_by_name(&names.0).map(|id| (id, names)))
+        .collect();
+
+    let mut changes: Vec<String> = added.iter().map(|note| {
+        let file_name = note.source.as_ref().and_then(|source| source.path.file_name())
+            .map_or(String::new(), |name| name.to_string_lossy().to_string());
+        format!("Add {} ({})", note.name, file_name)
+    }).collect();
+    changes.extend(renamed.iter().map(|(_, (old_name, new_name))| format!("Rename {} to {}", old_name, new_name)));
+    let Some(selected) = io_preview("Changes to make", &changes, &[("Couldn't be synced", report.skipped.clone())])? else {
+        return Ok(false);
+    };
+
+    let (added_selected, renamed_selected) = selected.split_at(added.len());
+    let unticked: HashSet<(&Path, usize)> = added.iter().zip(added_selected)
+        .filter(|(_, keep)| !**keep)
+        .filter_map(|(note, _)| note.source.as_ref())
+        .map(|source| (source.path.as_path(), source.position))
+        .collect();
+    plan.added.retain(|source| !unticked.contains(&(source.path.as_path(), source.position)));
+    for ((id, _), _) in renamed.iter().zip(renamed_selected).filter(|(_, keep)| !**keep) {
+        let Some(index) = plan.matched.iter().position(|(matched, _)| matched == id) else {
+            continue;
+        };
+        let old_text = note_map.get(id).and_then(|note| note.source.as_ref()).map(sync::source_text);
+        // A header matched by its place rather than its text is a different header, so gets its own note
+        // and the note for the old one is removed. Otherwise the note keeps its name
+        if old_text != Some(sync::source_text(&plan.matched[index].1)) {
+            let (id, source) = plan.matched.remove(index);
+            plan.added.push(source);
+            if note_map.get(&id).is_some_and(|note| !note.archived) {
+                plan.removed.push(id);
+            }
+        } else {
+            plan.keep_names.insert(*id);
+        }
     }
+    Ok(true)
+}
+
+// Reads every markdown file below the root to sync it. New notes get the given tags,
+// along with tags for the file name, its folders and its front matter
+fn read_sync_files(root: &Path, header_level: usize, tags: &BTreeSet<String>) -> Vec<sync::SyncFile> {
+    let mut files: Vec<sync::SyncFile> = vec![];
+    for path in markdown::get_markdown_files(root) {
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) => {
+                println!("{}", red_wrap!(format!("Skipped {}, {e}", bold_wrap!(path.display()))));
+                continue;
+            },
+        };
+        let mut file_tags = tags.clone();
+        file_tags.extend(folder_tags(&path, root));
+        file_tags.extend(file_name_tag(&path.to_string_lossy()));
+        file_tags.extend(front_matter::get_front_matter(&contents)
+            .map(|front_matter| front_matter::get_front_matter_list(&front_matter, "tags"))
+            .unwrap_or_default()
+            .iter().filter_map(|tag| tags::clean_tag(tag)));
+        files.push(sync::SyncFile {
+            name: path.file_stem().map_or(String::new(), |stem| stem.to_string_lossy().to_string()),
+            headings: markdown::get_headings(&contents, header_level),
+            tags: file_tags,
+            path,
+        });
+    }
+    files
+}
+
+// Lists the notes whose headers are no longer in the directory
+fn print_removed_headers(note_map: &NoteMap, removed: &[NoteId]) {
+    println!("{}", bold_wrap!("Headers no longer in the directory:"));
+    for name in removed.iter().filter_map(|id| note_map.get(id)).map(|note| &note.name).sorted() {
+        println!("\t{}", name);
+    }
+}
+
+// Lists the notes renamed with their headers, and any headers that couldn't be synced
+fn print_sync_changes(report: &sync::SyncReport) {
+    for (old_name, new_name) in &report.renamed {
+        println!("Renamed {} to {}", bold_wrap!(old_name), bold_wrap!(new_name));
+    }
+    for skipped in &report.skipped {
+        println!("{}", red_wrap!(skipped));
+    }
+}
+
+// Sums up what syncing a directory changed
+fn format_sync(plan: &sync::SyncPlan, report: &sync::SyncReport, files: usize, archived: bool) -> String {
+    let mut message = format!("{} new notes added and {} kept from {} files",
+        report.added, plan.matched.len(), files);
+    if !report.renamed.is_empty() {
+        message.push_str(&format!("\n{} notes renamed to match their headers", report.renamed.len()));
+    }
+    if report.moved > 0 {
+        message.push_str(&format!("\n{} files were moved or renamed, their notes kept their history", report.moved));
+    }
+    if report.restored > 0 {
+        message.push_str(&format!("\n{} archived notes had their headers come back and were restored", report.restored));
+    }
+    if !plan.removed.is_empty() {
+        message.push_str(&match archived {
+            true => format!("\n{} notes whose headers have gone were archived", plan.removed.len()),
+            false => format!("\n{} notes whose headers have gone were kept", plan.removed.len()),
+        });
+    }
+    message
+}
+
+// Imports an Obsidian vault, adding a note for each file, or a tree of notes for each file's headers.
+// Notes are tagged with the folders they're in, the tags in their front matter and any #tags in their text,
+// and are linked to the notes their [[wikilinks]] point to. Files with "review: false" are left out
+fn io_import_vault(note_map: &mut NoteMap) -> Result<String, MainError> {
+    let root = PathBuf::from(io_get_file_path("")?);
+    if !root.is_dir() {
+        return Err(MainError::Driver("Could not Find directory".to_string()));
+    }
+    let per_choices = ["File", "Header"];
+    let per_header = per_choices[select_wrapper("Add a note for each", &per_choices)?] == "Header";
+    // Headers aren't needed when adding a note per file
+    let header_level = match per_header {
+        true => io_get_header_level(&root.to_string_lossy())?,
+        false => 0,
+    };
+    let prefix = io_get_prefix()?;
+    let tags = io_get_tags("Enter tags for every new note, seperated by spaces (optional)\nFolder, front matter and #tags are added too", &BTreeSet::new())?;
+
+    // Each file to import and its notes, linked up once every file has been added
+    let mut files: Vec<obsidian::VaultFile> = vec![];
+    let mut imports: Vec<TreeImport> = vec![];
+    // Each alias to the name of the file it's for
+    let mut aliases: HashMap<String, String> = HashMap::new();
+    let mut opted_out = 0;
+    for path in obsidian::get_vault_files(&root) {
+        let file = match obsidian::read_vault_file(&path, header_level) {
+            Ok(file) => file,
+            Err(e) => {
+                println!("{}", red_wrap!(format!("Skipped {}, {e}", bold_wrap!(path.display()))));
+                continue;
+            },
+        };
+        if !file.review {
+            opted_out += 1;
+            continue;
+        }
+        let mut file_tags = tags.clone();
+        file_tags.extend(folder_tags(&path, &root));
+        file_tags.extend(file.tags.iter().filter_map(|tag| tags::clean_tag(tag)));
+        for alias in &file.aliases {
+            aliases.insert(name_key(alias), file.name.clone());
+        }
+        imports.push(TreeImport { path, headings: file.headings.clone(), prefix: prefix.clone(), tags: file_tags });
+        files.push(file);
+    }
+    if files.is_empty() && opted_out == 0 {
+        return Err(MainError::Driver("No markdown files were found in the vault".to_string()));
+    }
+    let Some(trees) = io_import_trees(&imports, note_map)? else {
+        return Err(MainError::Driver("Cancelled, no notes were added".to_string()));
+    };
+
+    let mut linked = 0;
+    let mut unresolved = 0;
+    for (file, tree) in files.iter().zip(&trees) {
+        for link in &file.links {
+            // Links belong to the header they're under.
+            // Notes that were already in the map are kept as they are
+            let Some(from) = file.headings.iter().zip(&tree.headings).rev()
+                .filter(|(heading, _)| heading.line <= link.line)
+                .find_map(|(_, id)| *id)
+                .or(tree.file)
+                .filter(|from| tree.added.contains(from)) else {
+                continue;
+            };
+            match resolve_wikilink(link, &file.name, &prefix, &aliases, note_map) {
+                Some(to) if to != from => {
+                    if note_map.get_mut(&from).is_some_and(|note| note.links.insert(to)) {
+                        linked += 1;
+                    }
+                },
+                Some(_) => (),
+                None => unresolved += 1,
+            }
+        }
+    }
+
+    let added: usize = trees.iter().map(|tree| tree.added.len()).sum();
+    let mut message = format!("{} new notes successfully added from {} files, with {} links",
+        added, files.len(), linked);
+    if opted_out > 0 {
+        message.push_str(&format!("\n{} files were left out by review: false", opted_out));
+    }
+    if unresolved > 0 {
+        message.push_str(&format!("\n{} links to files that weren't imported were left out", unresolved));
+    }
+    Ok(message)
+}
+
+// Finds the note a wikilink points to, by its file name or one of the file's aliases.
+// Links to a header go to the header's note if it's under the file, otherwise to the file
+fn resolve_wikilink(link: &obsidian::WikiLink, from_page: &str, prefix: &str, aliases: &HashMap<String, String>, note_map: &NoteMap) -> Option<NoteId> {
+    let page = match link.page.is_empty() {
+        true => from_page,
+        false => link.page.as_str(),
+    };
+    let page_id = note_map.id_by_name(&format!("{prefix}{page}"))
+        .or_else(|| aliases.get(&name_key(page)).and_then(|page| note_map.id_by_name(&format!("{prefix}{page}"))))?;
+    // Header notes are named after the notes above them, so they're found by the header they came from
+    link.heading.as_ref()
+        .and_then(|heading| note_map.subtree(&page_id).values()
+            .find(|note| note.source.as_ref()
+                .and_then(|source| source.heading.as_deref())
+                .is_some_and(|h| name_key(h) == name_key(heading)))
+            .map(|note| note.id))
+        .or(Some(page_id))
+}
+
+// Tags for each folder between the root and the file
+fn folder_tags(path: &Path, root: &Path) -> BTreeSet<String> {
+    path.parent().and_then(|p| p.strip_prefix(root).ok())
+        .map(|folders| folders.iter().filter_map(|f| tags::clean_tag(&f.to_string_lossy())).collect())
+        .unwrap_or_default()
+}
+
+// Gets the smallest header to include from a markdown file, 1 for H1 through to 6 for H6.
+// Used when adding and removing so the same file always gives the same names
+fn io_get_header_level(file_path: &str) -> Result<usize, MainError> {
+    let markdown_choices = ["H1 (#)", "H2 (##)", "H3 (###)", "H4 (####)", "H5 (#####)", "H6 (######)"];
+    Ok(select_wrapper(
+        format!("Whats the smallest header type you would like to include for file:{}", file_path).as_str(),
+        &markdown_choices
+    )? + 1)
 }
 
 // User options to get a prefix string
-fn io_get_prefix() -> String {
+fn io_get_prefix() -> Result<String, MainError> {
     let is_prefix = select_wrapper(
         "Would you like to add a prefix to the names?\n([prefix][NoteName])", 
-        YES_NO_CHOICES);
+        YES_NO_CHOICES)?;
     match YES_NO_CHOICES[is_prefix] {
         "YES" => {
-            Input::new()
+            Ok(Input::new()
             .with_prompt(format!("Enter prefix\n{}", 
                 bold_wrap!("Enter a seperator if desired, otherwise none are added")))
-            .interact()
-            .unwrap()
+            .interact()?)
+        },
+        _ => Ok("".to_string())
+    }
+}
+
+// Gets tags from the user, which can be left empty. Starts with the current tags filled in
+fn io_get_tags(prompt: &str, current: &BTreeSet<String>) -> Result<BTreeSet<String>, MainError> {
+    let input: String = Input::with_theme(&ColorfulTheme::default())
+        .with_prompt(prompt)
+        .with_initial_text(current.iter().join(" "))
+        .allow_empty(true)
+        .interact_text()?;
+    Ok(parse_tags(&input))
+}
+
+// Gets a tag expression to limit notes to, empty for every note
+fn io_get_tag_filter() -> Result<TagFilter, MainError> {
+    let input: String = Input::with_theme(&ColorfulTheme::default())
+        .with_prompt("Limit to notes with the tags, e.g. algo AND NOT graphs (leave empty for all notes)")
+        .allow_empty(true)
+        .interact_text()?;
+    Ok(TagFilter::parse(&input)?)
+}
+
+// Tag for notes imported from the given file, from its name without the extension
+fn file_name_tag(file_path: &str) -> Option<String> {
+    Path::new(file_path).file_stem().and_then(|stem| tags::clean_tag(&stem.to_string_lossy()))
+}
+
+// Formats tags for output, e.g. "algo, graphs"
+fn format_tags(tags: &BTreeSet<String>) -> String {
+    match tags.is_empty() {
+        true => "None".to_string(),
+        false => tags.iter().join(", "),
+    }
+}
+
+// Formats the names of the notes a note links to for output
+fn format_links(note_map: &NoteMap, links: &BTreeSet<NoteId>) -> String {
+    let names: Vec<&str> = links.iter()
+        .filter_map(|id| note_map.get(id))
+        .map(|note| note.name.as_str())
+        .sorted()
+        .collect();
+    match names.is_empty() {
+        true => "None".to_string(),
+        false => names.join(", "),
+    }
+}
+
+// Names of the notes kept in the map whose content or tags the preview map has replaced
+fn overwritten_names(note_map: &NoteMap, preview_map: &NoteMap) -> Vec<String> {
+    note_map.values()
+        .filter(|note| preview_map.get(&note.id).is_some_and(|preview| preview.name != note.name
+            || preview.tags != note.tags
+            || preview.parent != note.parent
+            || preview.links != note.links
+            || preview.source != note.source))
+        .map(|note| note.name.clone())
+        .collect()
+}
+
+// Lists what a bulk import or removal would change before anything is changed, along with the
+// names it would leave alone under their own headings. Then every change can be made, some
+// can be unticked first, or it can all be cancelled.
+// Returns whether to make each change, None if cancelled
+fn io_preview(title: &str, changes: &[String], unchanged: &[(&str, Vec<String>)]) -> Result<Option<Vec<bool>>, MainError> {
+    for (heading, names) in unchanged.iter().filter(|(_, names)| !names.is_empty()) {
+        println!("{}", bold_wrap!(format!("{} ({}):", heading, names.len())));
+        for name in names {
+            println!("\t{}", name);
+        }
+    }
+    if changes.is_empty() {
+        return Ok(Some(vec![]));
+    }
+    println!("{}", bold_wrap!(format!("{} ({}):", title, changes.len())));
+    for change in changes {
+        println!("\t{}", change);
+    }
+    let choices = ["Make every change", "Choose which to make", "Cancel"];
+    match choices[select_wrapper("Go ahead?", &choices)?] {
+        "Make every change" => Ok(Some(vec![true; changes.len()])),
+        "Choose which to make" => {
+            let picked = MultiSelect::with_theme(&ColorfulTheme::default())
+                .with_prompt("Untick any to leave out (space to tick, enter to go ahead)")
+                .items(changes)
+                .defaults(&vec![true; changes.len()])
+                .max_length(20)
+                .interact()?;
+            let mut selected = vec![false; changes.len()];
+            for index in picked {
+                selected[index] = true;
+            }
+            Ok(Some(selected))
         },
-        _ => "".to_string()
+        _ => Ok(None),
     }
 }
 
 // Wrapper around dialoger's Select struct
-fn select_wrapper<T: ToString>(prompt: &str, items: &[T]) -> usize {
-    Select::with_theme(&ColorfulTheme::default())
+fn select_wrapper<T: ToString>(prompt: &str, items: &[T]) -> Result<usize, MainError> {
+    Ok(Select::with_theme(&ColorfulTheme::default())
     .with_prompt(prompt)
     .items(items)
     .default(0)
-    .interact()
-    .unwrap()
+    .interact()?)
 }
 
 // Wrapper around dialoger's Input struct
-fn input_wrapper(prompt: &str) -> String {
-    Input::with_theme(&ColorfulTheme::default())
+fn input_wrapper(prompt: &str) -> Result<String, MainError> {
+    Ok(Input::with_theme(&ColorfulTheme::default())
                 .with_prompt(prompt)
-                .interact()
-                .unwrap()
+                .interact()?)
 }
 
 // Delete note with given name from map
-fn io_del_note(name: String, note_map: &mut HashMap<String, Note>) -> Result<String, MainError> {
-    if let Some(note_name) = note_map.keys()
-    .find(|key| key.to_lowercase() == name.to_lowercase()).cloned()
-    {
-        if let Some(note) = note_map.remove(&note_name) {
-            return Ok(format!(
-                "{} was removed with values:\nFreq: {}\nLast Accessed: {}",
-                note.name, note.freq, note.last_accessed
-            ));
-        } else {
-            return Err(MainError::DriverError(format!("Could not find note to remove of name {}", bold_wrap!(name))));
-        }
+fn io_del_note(name: String, note_map: &mut NoteMap) -> Result<String, MainError> {
+    match note_map.remove_by_name(&name) {
+        Some(note) => Ok(format!(
+            "{} was moved to the trash with values:\nFreq: {}\nLast Accessed: {}",
+            note.name, note.freq, format_time_for_output(&note.last_accessed)
+        )),
+        None => Err(MainError::Driver(format!("Could not find note to remove of name {}", bold_wrap!(name)))),
     }
-    Err(MainError::DriverError(format!("Could not find note to remove of name {}", bold_wrap!(name))))
 }
 
 // Edit note with given name from map
-fn io_edit_note_map(note_map: &mut HashMap<String, Note>) -> Result<String, MainError> {
+fn io_edit_note_map(note_map: &mut NoteMap) -> Result<String, MainError> {
     io_handle_empty_map(note_map)?;
     let search_option = ["Search", "Selection"];
-    let choice = select_wrapper("Search by name or selection", &search_option);
+    let choice = select_wrapper("Search by name or selection", &search_option)?;
     match search_option[choice] {
         "Search" => {
-            let name: String = input_wrapper("Enter Note Name");
+            let name: String = input_wrapper("Enter Note Name")?;
             // If note exists, display attributes and give user options
             // for editing notes name and freq
-            if let Some(note_name) = find_note_name(&name, note_map) {
-                let note: &mut Note = note_map.get_mut(&note_name).unwrap();
+            if let Some(note) = note_map.get_by_name(&name) {
                 println!("Name: {}\nFreq: {}\nLast Accessed: {}",
                     bold_wrap!(note.name),
                     bold_wrap!(note.freq),                    
                     bold_wrap!(format_time_for_output(&note.last_accessed))
                 );
-                io_edit_note(note);            
+                io_edit_note(note_map, note.id)?;
                 Ok("Note was updated".to_string())
             } else {
-                Err(MainError::DriverError("Couldn't find note".to_string()))
+                Err(MainError::Driver("Couldn't find note".to_string()))
             }
 
         },
-        "Selection" | _ =>  {
-            io_select_all_note(note_map);
+        _ =>  {
+            io_select_all_note(note_map)?;
             Ok("".to_string())
         }
     }
@@ -531,18 +2042,22 @@ fn io_edit_note_map(note_map: &mut HashMap<String, Note>) -> Result<String, Main
 
 
 // Opens editing an idividual note for the user
-fn io_edit_note(note: &mut Note) {
-    let attr = ["Name", "Freq", "Save"];
+fn io_edit_note(note_map: &mut NoteMap, id: NoteId) -> Result<(), MainError> {
+    let attr = ["Name", "Freq", "Tags", "Mark Reviewed", "Archived", "Save"];
     loop {        
         // Edit Note
-        match attr[select_wrapper("What would you like to edit?", &attr)] {
+        match attr[select_wrapper("What would you like to edit?", &attr)?] {
             "Name" => {
-                note.name = input_wrapper("Enter new name");
-                println!("{}", green_wrap!(format!("Name set to {}", bold_wrap!(note.name))));
+                let name = input_wrapper("Enter new name")?;
+                // Renamed through the map so the name stays unique
+                match note_map.rename(&id, &name) {
+                    Ok(name) => println!("{}", green_wrap!(format!("Name set to {}", bold_wrap!(name)))),
+                    Err(e) => println!("{}", red_wrap!(e)),
+                }
             },
             "Freq" => {
                 // Unwrap will always pass due to validator
-                note.freq = Input::with_theme(&ColorfulTheme::default())
+                let freq = Input::with_theme(&ColorfulTheme::default())
                         .with_prompt("Enter the freq")
                         .validate_with(|input: &String| -> Result<(),&str> {
                             match input.parse::<u16>() {
@@ -550,38 +2065,47 @@ fn io_edit_note(note: &mut Note) {
                                 Err(_) => Err("Must enter a positive number"),
                             }
                         })
-                        .interact()
-                        .unwrap().parse::<u16>().unwrap();
+                        .interact()?.parse::<u16>().unwrap();
+                let note = note_map.get_mut(&id).unwrap();
+                manual_note_update(note, freq, note.last_accessed);
                 println!("{}", green_wrap!(format!("Note Freq set to {}", bold_wrap!(note.freq))));
             },
-            "Save" | _ => {
-                return
+            "Tags" => {
+                let note = note_map.get_mut(&id).unwrap();
+                note.tags = io_get_tags("Enter tags, seperated by spaces", &note.tags)?;
+                println!("{}", green_wrap!(format!("Tags set to {}", bold_wrap!(format_tags(&note.tags)))));
+            },
+            "Mark Reviewed" => {
+                let note = note_map.get_mut(&id).unwrap();
+                manual_note_update(note, note.freq.saturating_add(1), Local::now());
+                println!("{}", green_wrap!(format!("{} marked as reviewed", bold_wrap!(note.name))));
+            },
+            "Archived" => {
+                let note = note_map.get_mut(&id).unwrap();
+                note.archived = !note.archived;
+                println!("{}", green_wrap!(match note.archived {
+                    true => format!("{} archived, it's left out of reviews", bold_wrap!(note.name)),
+                    false => format!("{} is no longer archived", bold_wrap!(note.name)),
+                }));
+            },
+            _ => {
+                return Ok(())
             }
        }
     }
 }
 
 // Input for browsing through all notes
-fn io_select_all_note(note_map: &mut HashMap<String, Note>) {
-    let mut all_notes: Vec<&mut Note> = note_map.values_mut().collect();    
+fn io_select_all_note(note_map: &mut NoteMap) -> Result<(), MainError> {
     loop {
-        let choice = select_wrapper("prompt", &all_notes);
-        io_edit_note(all_notes[choice]);
-        match YES_NO_CHOICES[select_wrapper("Edit Another?", YES_NO_CHOICES)] {
+        // Listed again each time, as names may have been edited
+        let all_notes: Vec<&Note> = note_map.values().sorted_by(|a, b| a.name.cmp(&b.name)).collect();
+        let id = all_notes[select_wrapper("prompt", &all_notes)?].id;
+        io_edit_note(note_map, id)?;
+        match YES_NO_CHOICES[select_wrapper("Edit Another?", YES_NO_CHOICES)?] {
             "YES" => (),
-            _ => return,
+            _ => return Ok(()),
         }
     }
     
 }
-
-// Finds the note in the map if it exists
-fn find_note_name(name: &str, note_map: &mut HashMap<String, Note>) -> Option<String> {
-    if let Some(note_name) = note_map.keys()
-        .find(|key| key.to_lowercase() == name.to_lowercase()).cloned()
-    {
-        Some(note_name)
-    } else {
-        None
-    }
-}
\ No newline at end of file
@@ -0,0 +1,127 @@
+// Timestamped copies of the notes file, kept in a backups folder next to it.
+// Only the newest few are kept, older ones are deleted as new ones are made
+use std::{fs, path::PathBuf};
+use chrono::{Local, NaiveDateTime};
+
+use super::{get_store_path, write_atomic, StorageError};
+
+// Format of the time in backup file names, e.g. notes-20240901-183000.123456.json.
+// Down to the microsecond, so backups made within the same second don't replace each other
+const BACKUP_TIME_FORMAT: &str = "%Y%m%d-%H%M%S%.6f";
+// Format older backups were named with, down to the second
+const LEGACY_BACKUP_TIME_FORMAT: &str = "%Y%m%d-%H%M%S";
+
+// A saved backup of the notes file
+pub struct Backup {
+    pub path: PathBuf,
+    pub time: NaiveDateTime,
+}
+
+// Folder backups of the current notes file are kept in
+pub fn get_backup_dir() -> PathBuf {
+    get_store_path().with_file_name("backups")
+}
+
+// Name backups of the current notes file start with, e.g. "notes-"
+fn backup_prefix() -> String {
+    let stem = get_store_path().file_stem()
+        .map_or("notes".to_string(), |s| s.to_string_lossy().to_string());
+    format!("{stem}-")
+}
+
+// Extension backups of the current notes file end with, matching the notes file, e.g. ".json"
+fn backup_suffix() -> String {
+    let extension = get_store_path().extension()
+        .map_or("json".to_string(), |e| e.to_string_lossy().to_string());
+    format!(".{extension}")
+}
+
+// Gets every backup of the current notes file, newest first
+pub fn list_backups() -> Result<Vec<Backup>, StorageError> {
+    let backup_dir = get_backup_dir();
+    if !backup_dir.is_dir() {
+        return Ok(vec![]);
+    }
+    let prefix = backup_prefix();
+    let mut backups: Vec<Backup> = vec![];
+    for entry in fs::read_dir(backup_dir)? {
+        let path = entry?.path();
+        let time = path.file_name()
+            .and_then(|n| n.to_str())
+            .and_then(|n| n.strip_prefix(&prefix))
+            .and_then(|n| n.strip_suffix(&backup_suffix()))
+            .and_then(parse_backup_time);
+        if let Some(time) = time {
+            backups.push(Backup { path, time });
+        }
+    }
+    backups.sort_by_key(|b| std::cmp::Reverse(b.time));
+    Ok(backups)
+}
+
+// Reads the time out of a backup's name, in either the current or the older format
+fn parse_backup_time(time: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(time, BACKUP_TIME_FORMAT)
+        .or_else(|_| NaiveDateTime::parse_from_str(time, LEGACY_BACKUP_TIME_FORMAT))
+        .ok()
+}
+
+// Copies the notes file into the backups folder, then deletes all but the newest `keep` backups.
+// Nothing is copied if the notes haven't changed since the last backup
+pub fn create_backup(keep: usize) -> Result<Option<PathBuf>, StorageError> {
+    let store_path = get_store_path();
+    if keep == 0 || !store_path.exists() {
+        return Ok(None);
+    }
+    let contents = fs::read(&store_path)?;
+    if contents.is_empty() {
+        return Ok(None);
+    }
+    let backups = list_backups()?;
+    if let Some(latest) = backups.first() {
+        if fs::read(&latest.path).is_ok_and(|latest| latest == contents) {
+            return Ok(None);
+        }
+    }
+
+    fs::create_dir_all(get_backup_dir())?;
+    let backup_path = get_backup_dir().join(format!(
+        "{}{}{}", backup_prefix(), Local::now().format(BACKUP_TIME_FORMAT), backup_suffix()));
+    write_atomic(&backup_path, &contents)?;
+
+    // Rotate out the oldest backups
+    for old in list_backups()?.into_iter().skip(keep) {
+        fs::remove_file(old.path)?;
+    }
+    Ok(Some(backup_path))
+}
+
+// Replaces the notes file with the given backup.
+// The current notes are backed up first so the restore can be undone
+pub fn restore_backup(backup: &Backup, keep: usize) -> Result<(), StorageError> {
+    let contents = fs::read(&backup.path)?;
+    create_backup(keep.max(1))?;
+    write_atomic(&get_store_path(), &contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backup_times_keep_sub_seconds() {
+        let earlier = parse_backup_time("20240901-183000.000100").unwrap();
+        let later = parse_backup_time("20240901-183000.250000").unwrap();
+        assert!(earlier < later);
+        let now = Local::now().naive_local();
+        let written = now.format(BACKUP_TIME_FORMAT).to_string();
+        assert_eq!(parse_backup_time(&written).unwrap().and_utc().timestamp_micros(), now.and_utc().timestamp_micros());
+    }
+
+    #[test]
+    fn older_backup_names_are_read() {
+        let time = parse_backup_time("20240901-183000").unwrap();
+        assert_eq!(time.format("%Y-%m-%d %H:%M:%S").to_string(), "2024-09-01 18:30:00");
+        assert!(parse_backup_time("backup").is_none());
+    }
+}
@@ -0,0 +1,102 @@
+// Reads the YAML front matter at the top of markdown files, e.g.
+// ---
+// tags: [algo, graphs]
+// ---
+// Only the simple layouts notes use are understood, rather than all of YAML
+use std::{fs, path::Path};
+
+use super::StorageError;
+
+// Lines between the opening and closing ---, None if the file has no front matter
+pub fn get_front_matter(contents: &str) -> Option<Vec<&str>> {
+    let mut lines = contents.lines();
+    if lines.next()?.trim_end() != "---" {
+        return None;
+    }
+    let mut front_matter: Vec<&str> = vec![];
+    for line in lines {
+        if line.trim_end() == "---" {
+            return Some(front_matter);
+        }
+        front_matter.push(line);
+    }
+    // Never closed, so it's not front matter
+    None
+}
+
+// Gets the values of a key, written either inline as "key: [a, b]" or "key: a, b",
+// or as a list of "- a" lines below the key
+pub fn get_front_matter_list(front_matter: &[&str], key: &str) -> Vec<String> {
+    let mut values: Vec<String> = vec![];
+    let mut lines = front_matter.iter();
+    while let Some(line) = lines.next() {
+        let Some(rest) = line.strip_prefix(key).and_then(|rest| rest.trim_start().strip_prefix(':')) else {
+            continue;
+        };
+        let rest = rest.trim();
+        if !rest.is_empty() {
+            values.extend(rest.trim_start_matches('[').trim_end_matches(']')
+                .split(',')
+                .map(unquote)
+                .filter(|value| !value.is_empty()));
+        } else {
+            // Indented "- value" lines, ending at the next key
+            for line in lines.by_ref() {
+                match line.trim().strip_prefix('-') {
+                    Some(value) => values.push(unquote(value)),
+                    None => break,
+                }
+            }
+        }
+        break;
+    }
+    values
+}
+
+// Gets the value of a single "key: value" line, None if the key isn't there
+pub fn get_front_matter_value(front_matter: &[&str], key: &str) -> Option<String> {
+    front_matter.iter()
+        .find_map(|line| line.strip_prefix(key).and_then(|rest| rest.trim_start().strip_prefix(':')))
+        .map(unquote)
+}
+
+fn unquote(value: &str) -> String {
+    value.trim().trim_matches(|c| c == '"' || c == '\'').to_string()
+}
+
+// Gets the tags listed in a markdown files front matter
+pub fn get_front_matter_tags(path: &Path) -> Result<Vec<String>, StorageError> {
+    let contents = fs::read_to_string(path)?;
+    Ok(get_front_matter(&contents)
+        .map(|front_matter| get_front_matter_list(&front_matter, "tags"))
+        .unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn front_matter_must_open_and_close() {
+        assert_eq!(get_front_matter("---\ntags: a\n---\n# Graphs"), Some(vec!["tags: a"]));
+        assert_eq!(get_front_matter("---\ntags: a\n# Graphs"), None);
+        assert_eq!(get_front_matter("# Graphs\n---\ntags: a\n---"), None);
+    }
+
+    #[test]
+    fn lists_are_read_inline_or_below_the_key() {
+        assert_eq!(get_front_matter_list(&["tags: [algo, \"graphs\"]"], "tags"), ["algo", "graphs"]);
+        assert_eq!(get_front_matter_list(&["tags: algo, graphs"], "tags"), ["algo", "graphs"]);
+        assert_eq!(get_front_matter_list(&["tags:", "  - algo", "  - 'graphs'", "review: false"], "tags"), ["algo", "graphs"]);
+        assert!(get_front_matter_list(&["aliases: [bfs]"], "tags").is_empty());
+        assert!(get_front_matter_list(&["tags: []"], "tags").is_empty());
+    }
+
+    #[test]
+    fn values_are_unquoted() {
+        let front_matter = ["review: false", "title: \"Graphs\""];
+        assert_eq!(get_front_matter_value(&front_matter, "review"), Some("false".to_string()));
+        assert_eq!(get_front_matter_value(&front_matter, "title"), Some("Graphs".to_string()));
+        assert_eq!(get_front_matter_value(&front_matter, "tags"), None);
+    }
+}
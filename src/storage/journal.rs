@@ -0,0 +1,156 @@
+// Journal of the changes made to the notes, so they can be undone and redone.
+// Each operation holds every note it changed as it was before and after,
+// so a bulk removal or import is undone in one step.
+// Saved next to the notes file so undo works across runs and from the command line
+use std::{collections::HashSet, fs, path::PathBuf};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use super::{get_store_path, timestamp, write_atomic, Note, NoteId, StorageError};
+
+// Most operations kept to undo, the oldest are dropped past this
+pub const JOURNAL_LENGTH: usize = 50;
+// Most bytes the operations to undo can take up once saved, the oldest are dropped past this.
+// Operations hold whole notes, history included, so a few bulk actions on a large collection add up
+pub const JOURNAL_SIZE: usize = 4 * 1024 * 1024;
+
+// Bumped when older journals can no longer be replayed, e.g. notes gaining ids
+const JOURNAL_VERSION: u64 = 1;
+
+// A note as it was before and after an operation.
+// None before means the note was added, None after means it was removed
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct NoteChange {
+    pub before: Option<Note>,
+    pub after: Option<Note>,
+}
+
+// A single undoable action, e.g. "Remove Notes Using File"
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Operation {
+    pub action: String,
+    #[serde(with = "timestamp")]
+    pub time: DateTime<Local>,
+    pub changes: Vec<NoteChange>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(default)]
+pub struct Journal {
+    // Journals from before versioning are version 0
+    pub version: u64,
+    // Oldest first, the last is the next to be undone
+    pub undo: Vec<Operation>,
+    // Operations that were undone, the last is the next to be redone
+    pub redo: Vec<Operation>,
+}
+
+impl Default for Journal {
+    fn default() -> Self {
+        Self { version: JOURNAL_VERSION, undo: vec![], redo: vec![] }
+    }
+}
+
+impl Journal {
+    // Adds a new operation to undo. Anything undone before it can no longer be redone
+    pub fn record(&mut self, action: &str, changes: Vec<NoteChange>) {
+        if changes.is_empty() {
+            return;
+        }
+        self.undo.push(Operation { action: action.to_string(), time: Local::now(), changes });
+        self.redo.clear();
+        self.trim();
+    }
+
+    // Forgets every change made to the given notes, e.g. once they're purged so undo can't bring them back.
+    // Operations left with no changes are dropped. Returns whether anything was forgotten
+    pub fn forget(&mut self, ids: &HashSet<NoteId>) -> bool {
+        let mut forgotten = false;
+        for operations in [&mut self.undo, &mut self.redo] {
+            for operation in operations.iter_mut() {
+                let before = operation.changes.len();
+                operation.changes.retain(|change| change.before.as_ref().or(change.after.as_ref())
+                    .is_none_or(|note| !ids.contains(&note.id)));
+                forgotten |= operation.changes.len() != before;
+            }
+            operations.retain(|operation| !operation.changes.is_empty());
+        }
+        forgotten
+    }
+
+    // Drops the oldest operations past JOURNAL_LENGTH or JOURNAL_SIZE.
+    // The newest is always kept, even on its own it's over the size, so the last action can be undone
+    fn trim(&mut self) {
+        let mut size = 0;
+        let keep = self.undo.iter().rev()
+            .take(JOURNAL_LENGTH)
+            .take_while(|operation| {
+                size += serde_json::to_vec(operation).map_or(0, |json| json.len());
+                size <= JOURNAL_SIZE
+            })
+            .count()
+            .max(1);
+        let dropped = self.undo.len().saturating_sub(keep);
+        self.undo.drain(..dropped);
+    }
+}
+
+// Gets the path of the journal for the current notes file
+pub fn get_journal_path() -> PathBuf {
+    get_store_path().with_file_name("journal.json")
+}
+
+// Loads the journal, empty if nothing has been journaled yet
+pub fn load_journal() -> Result<Journal, StorageError> {
+    let journal_path = get_journal_path();
+    if !journal_path.exists() {
+        return Ok(Journal::default());
+    }
+    let file = fs::read_to_string(journal_path)?;
+    if file.trim().is_empty() {
+        return Ok(Journal::default());
+    }
+    // Older journals are kept only until something new is journaled
+    let version = serde_json::from_str::<serde_json::Value>(&file)?
+        .get("version").and_then(|v| v.as_u64()).unwrap_or(0);
+    if version != JOURNAL_VERSION {
+        println!("Undo history from an older version of the tracker can't be used, so it was started again");
+        return Ok(Journal::default());
+    }
+    Ok(serde_json::from_str(&file)?)
+}
+
+pub fn save_journal(journal: &Journal) -> Result<(), StorageError> {
+    let json_string = serde_json::to_string(journal)?;
+    write_atomic(&get_journal_path(), json_string.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(name: &str) -> Vec<NoteChange> {
+        vec![NoteChange { before: None, after: Some(Note::new(name.to_string(), 0, Local::now())) }]
+    }
+
+    #[test]
+    fn oldest_operations_are_dropped() {
+        let mut journal = Journal::default();
+        for index in 0..JOURNAL_LENGTH + 5 {
+            journal.record(&format!("Add {index}"), change("Graphs"));
+        }
+        assert_eq!(journal.undo.len(), JOURNAL_LENGTH);
+        assert_eq!(journal.undo[0].action, "Add 5");
+
+        // Each of these is a third of the size allowed, so only the last two fit
+        let name = "x".repeat(JOURNAL_SIZE / 3);
+        for index in 0..3 {
+            journal.record(&format!("Import {index}"), change(&name));
+        }
+        assert_eq!(journal.undo.iter().map(|operation| operation.action.as_str()).collect::<Vec<_>>(), ["Import 1", "Import 2"]);
+        // Too large to fit at all, but kept so it can still be undone
+        journal.record("Import", change(&"x".repeat(JOURNAL_SIZE)));
+        assert_eq!(journal.undo.len(), 1);
+        assert!(journal.redo.is_empty());
+    }
+}
@@ -0,0 +1,72 @@
+// Advisory lock on a notes file, so two trackers open on the same notes
+// can't silently overwrite each others changes.
+// The lock is held on a .lock file next to the notes for as long as they're open,
+// and is released by the OS if the tracker exits without cleaning up
+use std::{
+    fs::{self, File, OpenOptions, TryLockError},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+};
+
+use super::StorageError;
+
+// Held for as long as the notes are open, dropping it releases the lock
+pub struct StoreLock {
+    file: File,
+}
+
+// Lock file of the given notes file, e.g. notes.json.lock
+pub fn get_lock_path(store_path: &Path) -> PathBuf {
+    let mut name = store_path.file_name().unwrap_or_default().to_os_string();
+    name.push(".lock");
+    store_path.with_file_name(name)
+}
+
+impl StoreLock {
+    // Locks the given notes file, erroring with StorageError::Locked
+    // if another tracker already has it open
+    pub fn acquire(store_path: &Path) -> Result<Self, StorageError> {
+        let lock_path = get_lock_path(store_path);
+        if let Some(parent) = lock_path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&lock_path)?;
+        match file.try_lock() {
+            Ok(()) => {},
+            Err(TryLockError::WouldBlock) => return Err(StorageError::Locked {
+                path: store_path.to_path_buf(),
+                pid: fs::read_to_string(&lock_path).ok().and_then(|pid| pid.trim().parse().ok()),
+            }),
+            Err(TryLockError::Error(e)) => return Err(e.into()),
+        }
+        // Record who holds the lock, so the error above can say
+        file.set_len(0)?;
+        write!(file, "{}", process::id())?;
+        file.flush()?;
+        Ok(Self { file })
+    }
+}
+
+impl Drop for StoreLock {
+    fn drop(&mut self) {
+        let _ = self.file.unlock();
+    }
+}
+
+// Whether another tracker has the given notes file open
+pub fn is_locked(store_path: &Path) -> io::Result<bool> {
+    let lock_path = get_lock_path(store_path);
+    if !lock_path.exists() {
+        return Ok(false);
+    }
+    let file = File::open(lock_path)?;
+    match file.try_lock_shared() {
+        Ok(()) => {
+            file.unlock()?;
+            Ok(false)
+        },
+        Err(TryLockError::WouldBlock) => Ok(true),
+        Err(TryLockError::Error(e)) => Err(e),
+    }
+}
@@ -0,0 +1,118 @@
+// Reads the headers out of markdown files with a CommonMark parser, so headers
+// in code blocks are left out, underlined (setext) headers are found, and names
+// are plain text without closing hashes or inline markup like **bold** or [links](url)
+use std::{fs, path::{Path, PathBuf}};
+use pulldown_cmark::{Event, Options, Parser, Tag, TagEnd};
+use walkdir::{DirEntry, WalkDir};
+
+use super::StorageError;
+
+// A header in a markdown file, level being 1 for H1 through to 6 for H6
+#[derive(Debug, Clone)]
+pub struct Heading {
+    pub name: String,
+    pub level: usize,
+    // Line the header starts on, counting from 1
+    pub line: usize,
+}
+
+// Gets the headers in the markdown in order, down to the given level,
+// so the structure of the file can be rebuilt from their levels.
+// Headers with no text are left out
+pub fn get_headings(contents: &str, max_level: usize) -> Vec<Heading> {
+    // Front matter is read as metadata, otherwise its closing --- would underline the line above as a header
+    let parser = Parser::new_ext(contents, Options::ENABLE_YAML_STYLE_METADATA_BLOCKS).into_offset_iter();
+    let mut headings: Vec<Heading> = vec![];
+    // Level, starting line and text so far of the header being read
+    let mut current: Option<(usize, usize, String)> = None;
+    for (event, range) in parser {
+        match event {
+            Event::Start(Tag::Heading { level, .. }) => {
+                current = Some((level as usize, line_number(contents, range.start), String::new()));
+            },
+            Event::End(TagEnd::Heading(_)) => {
+                if let Some((level, line, text)) = current.take() {
+                    let name = text.split_whitespace().collect::<Vec<&str>>().join(" ");
+                    if level <= max_level && !name.is_empty() {
+                        headings.push(Heading { name, level, line });
+                    }
+                }
+            },
+            Event::Text(text) | Event::Code(text) => {
+                if let Some((_, _, name)) = current.as_mut() {
+                    name.push_str(&text);
+                }
+            },
+            Event::SoftBreak | Event::HardBreak => {
+                if let Some((_, _, name)) = current.as_mut() {
+                    name.push(' ');
+                }
+            },
+            _ => (),
+        }
+    }
+    headings
+}
+
+// Line the given byte offset is on, counting from 1
+pub fn line_number(contents: &str, offset: usize) -> usize {
+    contents[..offset].matches('\n').count() + 1
+}
+
+// Gets the headers in the given markdown file, down to the given level
+pub fn get_headings_from_markdown(path: &str, max_level: usize) -> Result<Vec<Heading>, StorageError> {
+    if !Path::exists(Path::new(path)) {
+        return Err(StorageError::File("Could not find the file".to_string()));
+    }
+    Ok(get_headings(&fs::read_to_string(path)?, max_level))
+}
+
+// Gets every markdown file below the root, leaving out hidden folders like .git
+pub fn get_markdown_files(root: &Path) -> Vec<PathBuf> {
+    WalkDir::new(root).into_iter()
+        .filter_entry(|entry| entry.depth() == 0 || !(entry.file_type().is_dir() && entry.file_name().to_string_lossy().starts_with('.')))
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file() && entry.path().extension().is_some_and(|ext| ext == "md"))
+        .map(DirEntry::into_path)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(contents: &str, max_level: usize) -> Vec<(String, usize)> {
+        get_headings(contents, max_level).into_iter().map(|heading| (heading.name, heading.level)).collect()
+    }
+
+    #[test]
+    fn hashes_in_code_are_not_headers() {
+        let contents = "# Graphs\n```sh\n# not a header\n```\n    # indented code\n## BFS\n";
+        assert_eq!(names(contents, 6), [("Graphs".to_string(), 1), ("BFS".to_string(), 2)]);
+    }
+
+    #[test]
+    fn setext_headers_are_found() {
+        let contents = "Graphs\n======\n\nBFS\n---\ntext\n";
+        assert_eq!(names(contents, 6), [("Graphs".to_string(), 1), ("BFS".to_string(), 2)]);
+    }
+
+    #[test]
+    fn closing_hashes_and_markup_are_left_out() {
+        let contents = "# Graphs ##\n## **Breadth**  first `search` ###\n### [DFS](dfs.md)\n#\n";
+        assert_eq!(names(contents, 6), [
+            ("Graphs".to_string(), 1),
+            ("Breadth first search".to_string(), 2),
+            ("DFS".to_string(), 3),
+        ]);
+    }
+
+    #[test]
+    fn front_matter_and_deeper_headers_are_left_out() {
+        let contents = "---\ntags: [algo]\n---\n# Graphs\n### Deep\n";
+        let headings = get_headings(contents, 2);
+        assert_eq!(headings.len(), 1);
+        assert_eq!(headings[0].name, "Graphs");
+        assert_eq!(headings[0].line, 4);
+    }
+}
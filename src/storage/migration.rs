@@ -0,0 +1,170 @@
+// Upgrades older notes.json layouts to the current format version.
+// Each migration takes the file one version forward, so old files
+// are walked up version by version until they're current
+use serde_json::{json, Map, Value};
+
+use super::{NoteId, StorageError};
+
+// Version written by save_json_data
+pub const FORMAT_VERSION: u64 = 3;
+
+// Gets the format version of the loaded json.
+// Files from before versioning are an object keyed by index, which is version 1
+pub fn get_format_version(json_data: &Value) -> Result<u64, StorageError> {
+    match json_data.get("version") {
+        Some(version) => version.as_u64()
+            .ok_or(StorageError::Custom(format!("Unreadable format version {version}"))),
+        None if json_data.is_object() => Ok(1),
+        None => Err(StorageError::Custom("Notes file is not in a known format".to_string())),
+    }
+}
+
+// Migrates json data from the given version to FORMAT_VERSION
+pub fn migrate(mut json_data: Value, mut version: u64) -> Result<Value, StorageError> {
+    if version > FORMAT_VERSION {
+        return Err(StorageError::Custom(format!(
+            "Notes file is format version {version}, but this version of the tracker only reads up to {FORMAT_VERSION}. Try updating")));
+    }
+    while version < FORMAT_VERSION {
+        json_data = match version {
+            1 => v1_to_v2(json_data),
+            2 => v2_to_v3(json_data),
+            _ => return Err(StorageError::Custom(format!("No migration from format version {version}"))),
+        };
+        version += 1;
+    }
+    Ok(json_data)
+}
+
+// Version 1 stored notes in an object keyed by their index ("0", "1", ...).
+// Version 2 adds the version number and stores notes in a list
+fn v1_to_v2(json_data: Value) -> Value {
+    let notes: Vec<Value> = match json_data {
+        Value::Object(map) => map.into_iter()
+            .map(|(_, mut note)| {
+                if let Some(note) = note.as_object_mut() {
+                    seed_history(note);
+                }
+                note
+            })
+            .collect(),
+        _ => vec![],
+    };
+    json!({ "version": 2, "notes": notes })
+}
+
+// Some version 1 notes only kept the outcome of their last review, from before the full history.
+// It becomes the first entry of the history, dated when the note was last accessed
+fn seed_history(note: &mut Map<String, Value>) {
+    let Some(outcome) = note.remove("last_outcome").filter(|outcome| !outcome.is_null()) else {
+        return;
+    };
+    if note.get("history").and_then(Value::as_array).is_some_and(|history| !history.is_empty()) {
+        return;
+    }
+    let time = note.get("last_accessed").cloned().unwrap_or(Value::Null);
+    note.insert("history".to_string(), json!([
+        { "time": time, "outcome": outcome, "time_spent": null, "scheduler": null }
+    ]));
+}
+
+// Version 3 gives every note a stable id, so notes are no longer identified by name
+fn v2_to_v3(mut json_data: Value) -> Value {
+    if let Some(notes) = json_data["notes"].as_array_mut() {
+        for note in notes.iter_mut().filter_map(|note| note.as_object_mut()) {
+            note.entry("id").or_insert_with(|| json!(NoteId::new()));
+        }
+    }
+    json_data["version"] = json!(3);
+    json_data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{Note, ReviewOutcome};
+
+    fn v1_file() -> Value {
+        json!({
+            "0": { "name": "Graphs", "freq": 2, "last_accessed": "2024-09-01 18:30:00.000000000 +01:00" },
+            "1": { "name": "Trees", "freq": 0, "last_accessed": "2024-09-02 18:30:00.000000000 +01:00", "last_outcome": "Hard" },
+        })
+    }
+
+    fn note<'a>(json_data: &'a Value, name: &str) -> &'a Value {
+        json_data["notes"].as_array().unwrap().iter().find(|note| note["name"] == name).unwrap()
+    }
+
+    #[test]
+    fn reads_the_version() {
+        assert_eq!(get_format_version(&v1_file()).unwrap(), 1);
+        assert_eq!(get_format_version(&json!({ "version": 2, "notes": [] })).unwrap(), 2);
+        assert!(get_format_version(&json!([])).is_err());
+        assert!(get_format_version(&json!({ "version": "two" })).is_err());
+    }
+
+    #[test]
+    fn v1_to_v3_lists_the_notes_with_ids() {
+        let migrated = migrate(v1_file(), 1).unwrap();
+        assert_eq!(migrated["version"], FORMAT_VERSION);
+        let notes = migrated["notes"].as_array().unwrap();
+        assert_eq!(notes.len(), 2);
+        for note in notes {
+            let id = note["id"].as_str().unwrap();
+            assert!(NoteId::try_from(id.to_string()).is_ok(), "bad id {id}");
+        }
+        assert_ne!(note(&migrated, "Graphs")["id"], note(&migrated, "Trees")["id"]);
+        assert_eq!(note(&migrated, "Graphs")["freq"], 2);
+    }
+
+    #[test]
+    fn v1_last_outcome_starts_the_history() {
+        let migrated = migrate(v1_file(), 1).unwrap();
+        let trees = note(&migrated, "Trees");
+        assert!(trees.get("last_outcome").is_none());
+        assert_eq!(trees["history"], json!([{
+            "time": "2024-09-02 18:30:00.000000000 +01:00",
+            "outcome": "Hard",
+            "time_spent": null,
+            "scheduler": null,
+        }]));
+        assert!(note(&migrated, "Graphs").get("history").is_none());
+        let trees: Note = serde_json::from_value(trees.clone()).unwrap();
+        assert_eq!(trees.last_outcome(), Some(ReviewOutcome::Hard));
+    }
+
+    #[test]
+    fn v1_history_is_kept_over_last_outcome() {
+        let history = json!([{ "time": "2024-09-03T10:00:00+01:00", "outcome": "Recalled", "time_spent": 4, "scheduler": null }]);
+        let file = json!({
+            "0": { "name": "Graphs", "freq": 1, "last_accessed": "2024-09-03T10:00:00+01:00", "last_outcome": "Forgot", "history": history },
+            "1": { "name": "Trees", "freq": 0, "last_accessed": "2024-09-03T10:00:00+01:00", "last_outcome": null },
+        });
+        let migrated = migrate(file, 1).unwrap();
+        assert_eq!(note(&migrated, "Graphs")["history"], history);
+        assert!(note(&migrated, "Graphs").get("last_outcome").is_none());
+        assert!(note(&migrated, "Trees").get("history").is_none());
+    }
+
+    #[test]
+    fn v2_ids_are_kept() {
+        let file = json!({ "version": 2, "notes": [
+            { "id": "00000000000000ff", "name": "Graphs" },
+            { "name": "Trees" },
+        ]});
+        let migrated = migrate(file, 2).unwrap();
+        assert_eq!(note(&migrated, "Graphs")["id"], "00000000000000ff");
+        assert!(note(&migrated, "Trees")["id"].is_string());
+    }
+
+    #[test]
+    fn current_version_is_untouched() {
+        let file = json!({ "version": FORMAT_VERSION, "notes": [{ "name": "Graphs" }] });
+        assert_eq!(migrate(file.clone(), FORMAT_VERSION).unwrap(), file);
+    }
+
+    #[test]
+    fn newer_versions_error() {
+        assert!(migrate(json!({ "version": FORMAT_VERSION + 1, "notes": [] }), FORMAT_VERSION + 1).is_err());
+    }
+}
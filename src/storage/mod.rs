@@ -1,18 +1,34 @@
 use std::{
-    collections::BTreeMap, 
+    collections::{BTreeMap, BTreeSet},
     fmt::{self}, 
     fs,
     fs::File, 
-    path::Path,
+    path::{Path, PathBuf},
+    sync::RwLock,
     io::{self, BufRead, Write},
 };
-use itertools::Itertools;
+use chrono::{DateTime, Local};
 // JSON
 use serde::{Deserialize, Serialize};
 // Errors
 use thiserror::Error;
 
 use crate::bold_wrap;
+use crate::tracker::scheduler::SchedulerKind;
+
+pub mod timestamp;
+pub mod migration;
+pub mod backup;
+pub mod store;
+pub mod sqlite;
+pub mod lock;
+pub mod journal;
+pub mod trash;
+pub mod front_matter;
+pub mod markdown;
+pub mod obsidian;
+pub use markdown::{get_headings_from_markdown, Heading};
+use migration::FORMAT_VERSION;
 
 #[derive(Debug, Error)]
 pub enum StorageError {
@@ -22,112 +38,455 @@ pub enum StorageError {
     #[error("There was an Json Error: {0}")]
     SerdeJson(#[from] serde_json::Error),
 
+    #[error("There was an SQLite error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+
     #[error("There was  file error: {0}")]
     File(String),
 
+    #[error("{} is already open in another tracker{}", bold_wrap!(.path.display()),
+        .pid.map_or(String::new(), |pid| format!(" (process {pid})")))]
+    Locked { path: PathBuf, pid: Option<u32> },
+
+    #[error("Some notes could not be read:\n{}", .0.join("\n"))]
+    InvalidNotes(Vec<String>),
+
     #[error("There was an unexpected error: {0}")]
     Custom(String),
 }
 
+// Stable unique id of a note, which stays the same when it's renamed.
+// Written as 16 hex digits, e.g. "3f9a0c2e7b1d4e58"
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub struct NoteId(u64);
+
+impl NoteId {
+    // Makes a new random id
+    pub fn new() -> Self {
+        Self(rand::random())
+    }
+}
+
+impl Default for NoteId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for NoteId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+impl From<NoteId> for String {
+    fn from(id: NoteId) -> Self {
+        id.to_string()
+    }
+}
+
+impl TryFrom<String> for NoteId {
+    type Error = String;
+
+    fn try_from(id: String) -> Result<Self, Self::Error> {
+        u64::from_str_radix(&id, 16)
+            .map(NoteId)
+            .map_err(|_| format!("invalid note id \"{id}\", expected 16 hex digits"))
+    }
+}
+
 // Note type
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Note {
+    // Notes saved before ids existed are given one when first loaded
+    #[serde(default)]
+    pub id: NoteId,
+    // Unique, but can be changed at any time, unlike the id
     pub name: String,
     pub freq: u16,
-    pub last_accessed: String,
+    // Categories the note belongs to, used to filter reviews
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub tags: BTreeSet<String>,
+    // Note this one sits under, e.g. the file or header it was imported from.
+    // A parent that's been removed leaves the note at the top of the tree
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<NoteId>,
+    // Notes this one links to, e.g. from [[wikilinks]] in an Obsidian vault.
+    // Links to notes that have since been removed are ignored
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub links: BTreeSet<NoteId>,
+    // Markdown header the note was imported from, None for notes added by hand
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<NoteSource>,
+    // Archived notes are kept with their history but left out of reviews,
+    // e.g. when their header is removed from the markdown
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub archived: bool,
+    #[serde(with = "timestamp")]
+    pub last_accessed: DateTime<Local>,
+    #[serde(default)]
+    pub sm2: Sm2State,
+    #[serde(default)]
+    pub fsrs: FsrsState,
+    #[serde(default)]
+    pub leitner: LeitnerState,
+    // Every review or manual update, oldest first. Only ever appended to
+    #[serde(default)]
+    pub history: Vec<ReviewEntry>,
+}
+
+// Where in a markdown file a note was imported from, so syncing can find its header again
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct NoteSource {
+    pub path: PathBuf,
+    // Text of the header, None for the note made for the whole file
+    pub heading: Option<String>,
+    // Place of the header among the files imported headers counting from 1, 0 for the file
+    pub position: usize,
+}
+
+// A single logged review of a note
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct ReviewEntry {
+    #[serde(with = "timestamp")]
+    pub time: DateTime<Local>,
+    // None for manual updates
+    pub outcome: Option<ReviewOutcome>,
+    // Seconds spent grading the note, None for manual updates and rolled up reviews
+    pub time_spent: Option<u64>,
+    // Scheduler that suggested the note, None for manual updates
+    pub scheduler: Option<SchedulerKind>,
+}
+
+// How well a note was remembered when it was reviewed
+// Ordered best to worst
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ReviewOutcome {
+    Recalled,
+    Hard,
+    Forgot,
+}
+
+impl fmt::Display for ReviewOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReviewOutcome::Recalled => write!(f, "Recalled"),
+            ReviewOutcome::Hard => write!(f, "Hard"),
+            ReviewOutcome::Forgot => write!(f, "Forgot"),
+        }
+    }
+}
+
+// Spaced repetition values used by the SM-2 scheduler.
+// Defaulted for notes saved before SM-2 existed
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct Sm2State {
+    pub ease: f32,
+    // Days until the next review
+    pub interval: u32,
+    // Successful reviews in a row
+    pub repetitions: u32,
+    // None until the note is first reviewed with SM-2
+    #[serde(with = "timestamp::option")]
+    pub due: Option<DateTime<Local>>,
+}
+
+impl Default for Sm2State {
+    fn default() -> Self {
+        Self { ease: 2.5, interval: 0, repetitions: 0, due: None }
+    }
+}
+
+// Memory model used by the FSRS scheduler, built up from
+// each FSRS review of the note
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct FsrsState {
+    // Days for retrievability to fall to 90%
+    pub stability: f32,
+    // How hard the note is to remember, 1 (easy) to 10 (hard)
+    pub difficulty: f32,
+    // None until the note is first reviewed with FSRS
+    #[serde(with = "timestamp::option")]
+    pub last_review: Option<DateTime<Local>>,
+}
+
+// Which Leitner box the note is in, starting at box 1
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct LeitnerState {
+    pub box_number: usize,
+    // None until the note is first reviewed with Leitner boxes
+    #[serde(with = "timestamp::option")]
+    pub last_review: Option<DateTime<Local>>,
+}
+
+impl Default for LeitnerState {
+    fn default() -> Self {
+        Self { box_number: 1, last_review: None }
+    }
 }
 
 // Prints Note values, each on a new line
 impl fmt::Display for Note {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", format!(
+        write!(f,
             "Name: {}\nFreq: {}\nLast Accessed: {}",
-            self.name, self.freq, self.last_accessed))
+            self.name, self.freq, self.last_accessed.to_rfc3339())
     }
 }
 
-// Simple comparison of notes
+// Notes are the same note if they share an id, whatever their values
 impl PartialEq for Note {
     fn eq(&self, other: &Self) -> bool {
-        self.name == other.name
+        self.id == other.id
     }
 }
 // Constructor
 impl Note {
-    pub fn new(name: String, freq: u16, last_accessed: String) -> Self {
-        Self { name, freq, last_accessed }
+    // Whether any of the notes values differ from the saved copy.
+    // Unlike ==, which only compares ids
+    pub fn has_changed(&self, saved: &Note) -> bool {
+        // Destructured so new fields can't be missed here
+        let Note { id, name, freq, tags, parent, links, source, archived, last_accessed, sm2, fsrs, leitner, history } = self;
+        *id != saved.id || *name != saved.name || *freq != saved.freq || *tags != saved.tags
+            || *parent != saved.parent || *links != saved.links || *source != saved.source || *archived != saved.archived
+            || *last_accessed != saved.last_accessed
+            || *sm2 != saved.sm2 || *fsrs != saved.fsrs || *leitner != saved.leitner
+            || *history != saved.history
+    }
+
+    // Outcome of the most recent graded review, None if never reviewed
+    pub fn last_outcome(&self) -> Option<ReviewOutcome> {
+        self.history.iter().rev().find_map(|entry| entry.outcome)
+    }
+
+    pub fn new(name: String, freq: u16, last_accessed: DateTime<Local>) -> Self {
+        Self { id: NoteId::new(), name, freq, tags: BTreeSet::new(), parent: None, links: BTreeSet::new(),
+            source: None, archived: false, last_accessed, sm2: Sm2State::default(),
+            fsrs: FsrsState::default(), leitner: LeitnerState::default(), history: vec![] }
+    }
+}
+
+// Collection wide settings, saved next to the notes json file
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(default)]
+pub struct Settings {
+    pub scheduler: SchedulerKind,
+    // Chance of recall FSRS aims to keep notes above
+    pub fsrs_retention: f32,
+    // Days between reviews for each Leitner box, box 1 first
+    pub leitner_cadence: Vec<u32>,
+    // Number of timestamped backups of the notes to keep, 0 turns backups off
+    pub backup_count: usize,
+    // Days removed notes stay in the trash before being purged, 0 keeps them forever
+    pub trash_days: u32,
+    // Whether reviewing a note also reviews the note it sits under
+    pub roll_up_reviews: bool,
+    // Options each markdown directory was last synced with, by its full path
+    pub synced_dirs: BTreeMap<PathBuf, SyncOptions>,
+}
+
+// How a markdown directory is synced, kept so watching it adds notes the same way
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct SyncOptions {
+    // Smallest header notes are made from, 1 for H1 through to 6 for H6
+    pub header_level: usize,
+    pub prefix: String,
+    // Tags for new notes, as well as the ones from their file
+    pub tags: BTreeSet<String>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            scheduler: SchedulerKind::default(),
+            fsrs_retention: 0.9,
+            leitner_cadence: vec![1, 2, 4, 8, 16],
+            backup_count: 10,
+            trash_days: 30,
+            roll_up_reviews: false,
+            synced_dirs: BTreeMap::new(),
+        }
     }
 }
 
+// Notes file currently in use, set at start up and when switching profiles
+static STORE_PATH: RwLock<Option<PathBuf>> = RwLock::new(None);
+
 // Save Fucntions \\
 
-// Loads data from saved .json into a vector of note structs
-pub fn load_json_data() -> Result<Vec<Note>, StorageError>{     
+// Loads data from the given .json file into a vector of note structs.
+// When read only, nothing is written, so a missing file is empty and older formats are only upgraded in memory
+pub fn load_json_data(json_path: &Path, read_only: bool) -> Result<Vec<Note>, StorageError>{     
     // Check file path structure, if no file, create file
-    if !valid_json_path() {
-        create_json_file()?;        
+    if !valid_json_path(json_path) {
+        if read_only {
+            return Ok(vec![]);
+        }
+        create_json_file(json_path)?;        
     }
     
     // Read from file
-    let json_file_path = get_json_path();
-    let file = fs::read_to_string(json_file_path)?;
+    let file = fs::read_to_string(json_path)?;
 
     // If file is empty
-    if file.len() == 0 {
+    if file.is_empty() {
         Ok(vec![])
     } else {
         // Create json value
-        let json_data: serde_json::Value = serde_json::from_str(&file)?;        
-        // Load into vector
-        let notes: Vec<Note> = json_data.as_object().unwrap().values()
-        .map(|v| serde_json::from_value::<Note>(v.clone()).map_err(StorageError::from))
-        .collect::<Result<Vec<Note>, StorageError>>()?;
+        let mut json_data: serde_json::Value = serde_json::from_str(&file)?;
+
+        // Upgrade older formats, keeping a backup of the original file
+        let version = migration::get_format_version(&json_data)?;
+        if version != FORMAT_VERSION {
+            json_data = migration::migrate(json_data, version)?;
+            if !read_only {
+                let backup_path = json_path.with_extension(format!("json.v{version}.bak"));
+                fs::copy(json_path, &backup_path)?;
+                println!("Upgraded notes from format version {version} to {FORMAT_VERSION}, the original was saved to {}",
+                    bold_wrap!(backup_path.display()));
+            }
+        }
+
+        // Load into vector, collecting every note that can't be read
+        // so they can all be reported at once
+        let mut notes: Vec<Note> = vec![];
+        let mut invalid: Vec<String> = vec![];
+        let note_values = json_data["notes"].as_array().cloned().unwrap_or_default();
+        for (index, v) in note_values.into_iter().enumerate() {
+            match serde_json::from_value::<Note>(v.clone()) {
+                Ok(note) => notes.push(note),
+                Err(e) => {
+                    let name = v.get("name").and_then(|n| n.as_str())
+                        .map_or(format!("at position {index}"), |n| n.to_string());
+                    invalid.push(format!("Note {}: {e}", bold_wrap!(name)));
+                },
+            }
+        }
+        if !invalid.is_empty() {
+            return Err(StorageError::InvalidNotes(invalid));
+        }
+        if version != FORMAT_VERSION && !read_only {
+            save_json_data(json_path, notes.clone())?;
+        }
         Ok(notes)
     }
 }
 
-// Saves (writes) data from a vector of Note structs to a .json file
-// called "notes.json"
-pub fn save_json_data(note_data: Vec<Note>) -> Result<(), StorageError>{
-    let mut notes_map = BTreeMap::new();
+// Layout of the saved json file
+#[derive(Serialize)]
+struct NoteFile {
+    version: u64,
+    notes: Vec<Note>,
+}
 
-    // Load notes into tree
-    for (index, obj) in note_data.iter().enumerate() {
-        notes_map.insert(index.to_string(), obj);         
-    }
+// Saves (writes) data from a vector of Note structs to the given .json file,
+// sorted by name so the file changes as little as possible
+pub fn save_json_data(json_path: &Path, mut note_data: Vec<Note>) -> Result<(), StorageError>{
+    note_data.sort_by(|a, b| a.name.cmp(&b.name));
+    let note_file = NoteFile { version: FORMAT_VERSION, notes: note_data };
 
-    // Write tree to string
-    let json_string = serde_json::to_string_pretty(&notes_map)?;
+    // Write notes to string
+    let json_string = serde_json::to_string_pretty(&note_file)?;
 
     // Write string to the save file
-    let mut file = fs::File::create("notes.json")?;
-    file.write_all(json_string.as_bytes())?;
+    write_atomic(json_path, json_string.as_bytes())
+}
+
+// Writes to a temporary file next to the given path, then renames it into place.
+// Either the old or the new contents are left if the write fails part way through,
+// and the temporary file is cleaned up
+pub fn write_atomic(path: &Path, contents: &[u8]) -> Result<(), StorageError> {
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+
+    if let Err(e) = write_and_rename(&tmp_path, path, contents) {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(e.into());
+    }
+    // The rename is only kept through a power cut once the folder it's in is on disk too
+    sync_dir(path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new(".")))?;
+    Ok(())
+}
+
+fn write_and_rename(tmp_path: &Path, path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut file = fs::File::create(tmp_path)?;
+    file.write_all(contents)?;
     file.flush()?;
+    // Make sure the data is on disk before the rename makes it the real file
+    file.sync_all()?;
+    drop(file);
+    fs::rename(tmp_path, path)
+}
 
+// Flushes a folders entries to disk. Folders can't be opened as files on Windows,
+// where renames are written through anyway
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    File::open(dir)?.sync_all()?;
+    #[cfg(not(unix))]
+    let _ = dir;
     Ok(())
 }
 
-// Gets json path. Hardcoded for now
-// but once upon a time there was a plan to 
-// set this to a config file. It seems better
-// to not allow the user to mess with save files though
-pub fn get_json_path() -> String {
-    "notes.json".to_string()
+// Sets the notes file used from now on.
+// The current map should be saved first, as it won't be written to the old file after
+pub fn set_store_path(path: PathBuf) {
+    *STORE_PATH.write().unwrap() = Some(path);
+}
+
+// Gets the notes file path, resolved at start up by config::resolve_store_path.
+// Falls back to notes.json in the current directory if it was never set
+pub fn get_store_path() -> PathBuf {
+    STORE_PATH.read().unwrap().clone().unwrap_or_else(|| PathBuf::from("notes.json"))
 } 
 
 // Check path exists
-pub fn valid_json_path() -> bool {
-    Path::exists(Path::new("notes.json"))
+pub fn valid_json_path(json_path: &Path) -> bool {
+    Path::exists(json_path)
 }
 
-// Create the json file
-pub fn create_json_file() -> Result<(), StorageError> {
-    match fs::File::create_new("notes.json") {
+// Create the json file, along with any missing parent folders
+pub fn create_json_file(json_path: &Path) -> Result<(), StorageError> {
+    if let Some(parent) = json_path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    match fs::File::create_new(json_path) {
         Ok(_) => Ok(()),
         Err(e) => Err(StorageError::File(format!("Could not create file. Error: {e}"))),
     }
 }
 
+// Gets the settings path, kept in the same folder as the notes
+pub fn get_settings_path() -> PathBuf {
+    get_store_path().with_file_name("settings.json")
+}
+
+// Loads the collections settings, falling back to the
+// defaults if none have been saved yet
+pub fn load_settings() -> Result<Settings, StorageError> {
+    let settings_path = get_settings_path();
+    if !Path::exists(Path::new(&settings_path)) {
+        return Ok(Settings::default());
+    }
+    let file = fs::read_to_string(settings_path)?;
+    if file.trim().is_empty() {
+        Ok(Settings::default())
+    } else {
+        Ok(serde_json::from_str(&file)?)
+    }
+}
+
+// Saves (writes) the collections settings
+pub fn save_settings(settings: &Settings) -> Result<(), StorageError> {
+    let json_string = serde_json::to_string_pretty(settings)?;
+    write_atomic(&get_settings_path(), json_string.as_bytes())
+}
+
 // Loads note names from the given file per line
 pub fn get_note_names_from_file(path: &str) -> Result<Vec<String>, StorageError> {
     if !Path::exists(Path::new(path)) {
@@ -135,7 +494,7 @@ pub fn get_note_names_from_file(path: &str) -> Result<Vec<String>, StorageError>
     }
     let mut names: Vec<String> = vec![];
     if let Ok(lines) = read_lines(path) {
-        for line in lines.flatten() {
+        for line in lines.map_while(Result::ok) {
             if !line.trim().is_empty() {
                 names.push(line.trim().to_string());
             }
@@ -145,55 +504,43 @@ pub fn get_note_names_from_file(path: &str) -> Result<Vec<String>, StorageError>
 }
 
 
-// Gets note names from headers in the given markdown file. 
-// Min_hashes are the min type of header to inlcude
-pub fn get_note_names_from_markdown(path: &str, min_hashes: usize) -> Result<Vec<String>, StorageError> {
-    if !Path::exists(Path::new(path)) {
-        return Err(StorageError::File("Could not find the file".to_string()));
-    }
-
-    let mut names: Vec<String> = vec![];
-    if let Ok(lines) = read_lines(path) {
-        for line in lines.flatten() {
-            if let Some(name) = parse_markdown_headers_from_line(line.trim(), min_hashes) {
-                println!("File Note name: {}", bold_wrap!(name));
-                names.push(name);
-            }
-        }
-    };
-    Ok(names)
+fn read_lines<P>(filename: P) -> io::Result<io::Lines<io::BufReader<File>>>
+where P: AsRef<Path>, {
+    let file = File::open(filename)?;
+    Ok(io::BufReader::new(file).lines())
 }
 
-// Gets the text from markdown headers if in the given line
-// Extracts names based on givin min_hashes
-fn parse_markdown_headers_from_line(line: &str, min_hashes: usize) -> Option<String> {
-    let mut hashes = 0;    
-    let mut char_indicies = line.char_indices();
-    while let Some((_, char)) = char_indicies.next() {
-        if ![' ','#'].contains(&char) {
-            return None;
-        } else {
-            if char == '#'{
-                hashes += 1;
-                while let Some((_, new_char)) = char_indicies.next() {
-                    if new_char == '#' {
-                        hashes +=1;
-                    } else if new_char != ' ' || hashes > min_hashes{
-                        return None;
-                    } else {
-                        break;
-                    }
-                }
-                return Some(char_indicies.map(|(_,c)| c).collect_vec().into_iter().join(""));
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
 
-            }
-        }
+    // Empty folder for a test to write into
+    pub(crate) fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("notes_tracker_{}_{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
     }
-    None
-}
 
-fn read_lines<P>(filename: P) -> io::Result<io::Lines<io::BufReader<File>>>
-where P: AsRef<Path>, {
-    let file = File::open(filename)?;
-    Ok(io::BufReader::new(file).lines())
+    #[test]
+    fn write_atomic_replaces_the_file() {
+        let dir = test_dir("write_atomic");
+        let path = dir.join("notes.json");
+        write_atomic(&path, b"old").unwrap();
+        write_atomic(&path, b"new").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+        assert!(!dir.join("notes.json.tmp").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn write_atomic_cleans_up_when_it_fails() {
+        let dir = test_dir("write_atomic_fails");
+        // A folder can't be replaced by a file, so the rename fails
+        let path = dir.join("notes.json");
+        fs::create_dir(&path).unwrap();
+        assert!(write_atomic(&path, b"new").is_err());
+        assert!(!dir.join("notes.json.tmp").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
 }
@@ -0,0 +1,223 @@
+// Reads Obsidian vaults, folders of markdown files linked together with [[wikilinks]].
+// The tags and aliases in each files front matter are read along with inline #tags and links,
+// so notes imported from a file keep them. The .obsidian settings folder, other hidden
+// folders and attachment folders are skipped
+use std::{fs, path::{Path, PathBuf}};
+use pulldown_cmark::{Event, LinkType, Options, Parser, Tag, TagEnd, TextMergeWithOffset};
+use walkdir::{DirEntry, WalkDir};
+
+use super::{front_matter, markdown::{get_headings, line_number, Heading}, StorageError};
+
+// Folders attachments are usually kept in, skipped even if the vault doesn't name them
+const ATTACHMENT_FOLDERS: &[&str] = &["attachments", "assets"];
+
+// A [[wikilink]] to another file, or to a header within one
+#[derive(Debug, Clone)]
+pub struct WikiLink {
+    // Name of the linked file without .md, empty for headers in the same file
+    pub page: String,
+    pub heading: Option<String>,
+    // Line the link is on, counting from 1
+    pub line: usize,
+}
+
+// Everything read from a file in the vault
+#[derive(Debug, Clone)]
+pub struct VaultFile {
+    // File name without .md, which is what links use
+    pub name: String,
+    // Front matter and inline #tags, as they were written
+    pub tags: Vec<String>,
+    // Other names links can use for the file
+    pub aliases: Vec<String>,
+    // False when the front matter opts out with "review: false"
+    pub review: bool,
+    pub headings: Vec<Heading>,
+    pub links: Vec<WikiLink>,
+}
+
+// Where the vault keeps attachments, from its settings
+enum AttachmentFolder {
+    // Kept next to the notes or at the root, so no folder is skipped for them
+    Unset,
+    // One folder, relative to the root of the vault
+    Path(PathBuf),
+    // A folder with this name below each note
+    Named(String),
+}
+
+// Gets every markdown file in the vault, leaving out hidden and attachment folders
+pub fn get_vault_files(root: &Path) -> Vec<PathBuf> {
+    let attachments = get_attachment_folder(root);
+    WalkDir::new(root).into_iter()
+        .filter_entry(|entry| entry.depth() == 0 || !is_skipped_folder(entry, root, &attachments))
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file() && entry.path().extension().is_some_and(|ext| ext == "md"))
+        .map(DirEntry::into_path)
+        .collect()
+}
+
+// Reads the attachment folder from .obsidian/app.json, Unset if it can't be read
+fn get_attachment_folder(root: &Path) -> AttachmentFolder {
+    let folder = fs::read_to_string(root.join(".obsidian").join("app.json")).ok()
+        .and_then(|file| serde_json::from_str::<serde_json::Value>(&file).ok())
+        .and_then(|settings| settings.get("attachmentFolderPath")?.as_str().map(|s| s.to_string()));
+    match folder.as_deref().map(|f| f.trim_end_matches('/')) {
+        None | Some("" | "." | "/") => AttachmentFolder::Unset,
+        Some(folder) => match folder.strip_prefix("./") {
+            Some(name) => AttachmentFolder::Named(name.to_string()),
+            None => AttachmentFolder::Path(PathBuf::from(folder)),
+        },
+    }
+}
+
+fn is_skipped_folder(entry: &DirEntry, root: &Path, attachments: &AttachmentFolder) -> bool {
+    if !entry.file_type().is_dir() {
+        return false;
+    }
+    let name = entry.file_name().to_string_lossy();
+    name.starts_with('.')
+        || ATTACHMENT_FOLDERS.contains(&name.to_lowercase().as_str())
+        || match attachments {
+            AttachmentFolder::Unset => false,
+            AttachmentFolder::Path(folder) => entry.path().strip_prefix(root).is_ok_and(|path| path == folder),
+            AttachmentFolder::Named(folder) => name == folder.as_str(),
+        }
+}
+
+// Reads a file in the vault, getting its headers down to the given level
+pub fn read_vault_file(path: &Path, max_level: usize) -> Result<VaultFile, StorageError> {
+    let contents = fs::read_to_string(path)?;
+    let front_matter = front_matter::get_front_matter(&contents).unwrap_or_default();
+    let mut tags = front_matter::get_front_matter_list(&front_matter, "tags");
+    let aliases = front_matter::get_front_matter_list(&front_matter, "aliases");
+    let review = front_matter::get_front_matter_value(&front_matter, "review")
+        .is_none_or(|review| !["false", "no", "off"].contains(&review.to_lowercase().as_str()));
+
+    let (links, inline_tags) = get_links_and_tags(&contents);
+    tags.extend(inline_tags);
+    Ok(VaultFile {
+        name: path.file_stem().map_or(String::new(), |stem| stem.to_string_lossy().to_string()),
+        tags,
+        aliases,
+        review,
+        headings: get_headings(&contents, max_level),
+        links,
+    })
+}
+
+// Gets the wikilinks and inline #tags in the markdown, leaving out any in code
+fn get_links_and_tags(contents: &str) -> (Vec<WikiLink>, Vec<String>) {
+    let options = Options::ENABLE_YAML_STYLE_METADATA_BLOCKS | Options::ENABLE_WIKILINKS;
+    let parser = TextMergeWithOffset::new(Parser::new_ext(contents, options).into_offset_iter());
+    let mut links: Vec<WikiLink> = vec![];
+    let mut tags: Vec<String> = vec![];
+    // How many code blocks, front matter blocks or links the text is in, where #s aren't tags
+    let mut skipping = 0;
+    // Last character before the text, kept across inline events so "**x**#tag" isn't a tag
+    let mut previous = ' ';
+    for (event, range) in parser {
+        match event {
+            Event::Start(Tag::Link { link_type, dest_url, .. } | Tag::Image { link_type, dest_url, .. }) => {
+                skipping += 1;
+                // Embedded notes, ![[note]], count as links too
+                if matches!(link_type, LinkType::WikiLink { .. }) {
+                    links.extend(parse_wikilink(&dest_url, line_number(contents, range.start)));
+                }
+            },
+            Event::Start(Tag::CodeBlock(_) | Tag::MetadataBlock(_)) => {
+                skipping += 1;
+                previous = ' ';
+            },
+            Event::End(TagEnd::Link | TagEnd::Image) => {
+                skipping -= 1;
+                previous = ']';
+            },
+            Event::End(TagEnd::CodeBlock | TagEnd::MetadataBlock(_)) => {
+                skipping -= 1;
+                previous = ' ';
+            },
+            // Emphasis is inside the same line, so the text either side of it runs on
+            Event::Start(Tag::Emphasis | Tag::Strong | Tag::Strikethrough | Tag::Superscript | Tag::Subscript)
+            | Event::End(TagEnd::Emphasis | TagEnd::Strong | TagEnd::Strikethrough | TagEnd::Superscript | TagEnd::Subscript) => (),
+            // Any other tag starts or ends a block, as do line breaks
+            Event::Start(_) | Event::End(_) | Event::SoftBreak | Event::HardBreak | Event::Rule => previous = ' ',
+            Event::Text(text) if skipping == 0 => tags.extend(get_inline_tags(&text, &mut previous)),
+            Event::Code(text) | Event::InlineHtml(text) | Event::Html(text) | Event::Text(text) => {
+                previous = text.chars().last().unwrap_or(previous);
+            },
+            _ => (),
+        }
+    }
+    (links, tags)
+}
+
+// Splits a link such as "maths/Limits#Proof" into the file and header,
+// None for links to attachments like images
+fn parse_wikilink(target: &str, line: usize) -> Option<WikiLink> {
+    let (page, heading) = match target.split_once('#') {
+        Some((page, heading)) => (page, Some(heading)),
+        None => (target, None),
+    };
+    // Block references, e.g. [[Limits#^a1b2]], link to the file
+    let heading = heading
+        .map(|heading| heading.trim())
+        .filter(|heading| !heading.is_empty() && !heading.starts_with('^'))
+        .map(|heading| heading.to_string());
+    // Only the file name is needed, as Obsidian's file names are unique
+    let page = page.trim().rsplit('/').next().unwrap_or_default();
+    let page = match page.rsplit_once('.') {
+        Some((stem, "md")) => stem,
+        // Short extensions like .png or .pdf are attachments, other dots are part of the name
+        Some((_, extension)) if extension.len() <= 4 && extension.chars().all(|c| c.is_ascii_alphanumeric()) => return None,
+        _ => page,
+    };
+    if page.is_empty() && heading.is_none() {
+        return None;
+    }
+    Some(WikiLink { page: page.to_string(), heading, line })
+}
+
+// Gets the #tags written in text, e.g. "see #graphs".
+// Tags can't be only numbers, so "#1" isn't one. previous is the character before the text,
+// and is left as the last one in it
+fn get_inline_tags(text: &str, previous: &mut char) -> Vec<String> {
+    let mut tags: Vec<String> = vec![];
+    for (index, c) in text.char_indices() {
+        if c == '#' && previous.is_whitespace() {
+            let tag: String = text[index + 1..].chars()
+                .take_while(|c| c.is_alphanumeric() || ['_', '-', '/'].contains(c))
+                .collect();
+            if tag.chars().any(|c| !c.is_numeric()) {
+                tags.push(tag);
+            }
+        }
+        *previous = c;
+    }
+    tags
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(contents: &str) -> Vec<String> {
+        get_links_and_tags(contents).1
+    }
+
+    #[test]
+    fn tags_need_a_space_before_them() {
+        assert_eq!(tags("see #graphs\n\n#trees and c#sharp"), ["graphs", "trees"]);
+        assert_eq!(tags("#1 but #2d"), ["2d"]);
+    }
+
+    #[test]
+    fn tags_run_on_from_other_markdown() {
+        assert!(tags("**x**#tag").is_empty());
+        assert!(tags("`code`#tag").is_empty());
+        assert!(tags("[[Limits]]#tag").is_empty());
+        assert_eq!(tags("**bold** #tag and *#emphasis*"), ["tag", "emphasis"]);
+        // Each line or block starts afresh
+        assert_eq!(tags("`code`\n#line\n\n- x\n- #item"), ["line", "item"]);
+    }
+}
@@ -0,0 +1,266 @@
+// Embedded SQLite backend, for collections too large to rewrite on every change.
+// Each notes values are kept as json so new fields don't need a schema change,
+// while history gets its own table so reviews can be appended cheaply
+use std::{collections::HashMap, path::Path};
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::bold_wrap;
+use super::{store::NoteStore, timestamp, Note, NoteId, ReviewEntry, StorageError};
+
+// Bumped whenever the tables change, stored in sqlite's user_version
+const SCHEMA_VERSION: i64 = 2;
+
+pub struct SqliteStore {
+    conn: Connection,
+}
+
+impl SqliteStore {
+    // Opens the database, creating it and its tables if needed.
+    // Read only databases are never created or migrated, so they have to exist on the current schema
+    pub fn open(path: &Path, read_only: bool) -> Result<Self, StorageError> {
+        if read_only {
+            if !path.is_file() {
+                return Err(StorageError::Custom(format!("There is no database at {}", bold_wrap!(path.display()))));
+            }
+            let conn = Connection::open_with_flags(path,
+                OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_URI | OpenFlags::SQLITE_OPEN_NO_MUTEX)?;
+            let version = schema_version(&conn)?;
+            if version < SCHEMA_VERSION {
+                return Err(StorageError::Custom(format!(
+                    "Database is schema version {version} and needs upgrading to {SCHEMA_VERSION}, which can't be done while it's read only")));
+            }
+            return Ok(Self { conn });
+        }
+
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut conn = Connection::open(path)?;
+        conn.execute_batch("PRAGMA foreign_keys = ON;")?;
+
+        if schema_version(&conn)? == 1 {
+            v1_to_v2(&mut conn)?;
+        }
+        create_tables(&conn)?;
+        Ok(Self { conn })
+    }
+}
+
+// Gets the databases schema version, 0 for a new database
+fn schema_version(conn: &Connection) -> Result<i64, StorageError> {
+    let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    if version > SCHEMA_VERSION {
+        return Err(StorageError::Custom(format!(
+            "Database is schema version {version}, but this version of the tracker only reads up to {SCHEMA_VERSION}. Try updating")));
+    }
+    Ok(version)
+}
+
+fn create_tables(conn: &Connection) -> Result<(), StorageError> {
+    conn.execute_batch(&format!("
+        CREATE TABLE IF NOT EXISTS notes (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL,
+            data TEXT NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS notes_name ON notes(name);
+        CREATE TABLE IF NOT EXISTS history (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            note_id TEXT NOT NULL REFERENCES notes(id) ON DELETE CASCADE,
+            time TEXT NOT NULL,
+            outcome TEXT,
+            time_spent INTEGER,
+            scheduler TEXT
+        );
+        CREATE INDEX IF NOT EXISTS history_note ON history(note_id);
+        PRAGMA user_version = {SCHEMA_VERSION};
+    "))?;
+    Ok(())
+}
+
+// Version 1 keyed notes and their history by name.
+// Version 2 keys them by id, so each note is given one here
+fn v1_to_v2(conn: &mut Connection) -> Result<(), StorageError> {
+    let tx = conn.transaction()?;
+    tx.execute_batch("
+        ALTER TABLE notes RENAME TO notes_v1;
+        ALTER TABLE history RENAME TO history_v1;
+        DROP INDEX IF EXISTS history_note;
+    ")?;
+    create_tables(&tx)?;
+    {
+        let mut stmt = tx.prepare("SELECT name, data FROM notes_v1")?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let name: String = row.get(0)?;
+            let data: String = row.get(1)?;
+            tx.execute("INSERT INTO notes (id, name, data) VALUES (?1, ?2, ?3)",
+                params![NoteId::new().to_string(), name, data])?;
+        }
+    }
+    tx.execute_batch("
+        INSERT INTO history (note_id, time, outcome, time_spent, scheduler)
+            SELECT notes.id, h.time, h.outcome, h.time_spent, h.scheduler
+            FROM history_v1 h JOIN notes ON notes.name = h.note_name
+            ORDER BY h.id;
+        DROP TABLE history_v1;
+        DROP TABLE notes_v1;
+    ")?;
+    tx.commit()?;
+    Ok(())
+}
+
+// Converts a notes values to json, leaving out the history which has its own table
+fn note_data(note: &Note) -> Result<String, StorageError> {
+    let mut data = serde_json::to_value(note)?;
+    if let Some(map) = data.as_object_mut() {
+        map.remove("history");
+    }
+    Ok(data.to_string())
+}
+
+// Stores unit enums (outcomes, schedulers) by their serde name, e.g. "Recalled"
+fn enum_to_sql<T: Serialize>(value: &Option<T>) -> Result<Option<String>, StorageError> {
+    match value {
+        Some(value) => Ok(serde_json::to_value(value)?.as_str().map(|s| s.to_string())),
+        None => Ok(None),
+    }
+}
+
+fn enum_from_sql<T: DeserializeOwned>(value: Option<String>) -> Result<Option<T>, StorageError> {
+    match value {
+        Some(value) => Ok(Some(serde_json::from_value(serde_json::Value::String(value))?)),
+        None => Ok(None),
+    }
+}
+
+fn insert_history(conn: &Connection, id: NoteId, entry: &ReviewEntry) -> Result<(), StorageError> {
+    conn.execute(
+        "INSERT INTO history (note_id, time, outcome, time_spent, scheduler) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            id.to_string(),
+            entry.time.to_rfc3339(),
+            enum_to_sql(&entry.outcome)?,
+            entry.time_spent,
+            enum_to_sql(&entry.scheduler)?,
+        ])?;
+    Ok(())
+}
+
+fn insert_note(conn: &Connection, note: &Note) -> Result<(), StorageError> {
+    conn.execute("INSERT INTO notes (id, name, data) VALUES (?1, ?2, ?3)",
+        params![note.id.to_string(), note.name, note_data(note)?])?;
+    for entry in &note.history {
+        insert_history(conn, note.id, entry)?;
+    }
+    Ok(())
+}
+
+impl NoteStore for SqliteStore {
+    fn load(&mut self) -> Result<Vec<Note>, StorageError> {
+        // Read every notes history first, in the order it was logged
+        let mut histories: HashMap<String, Vec<ReviewEntry>> = HashMap::new();
+        let mut invalid: Vec<String> = vec![];
+        let mut stmt = self.conn.prepare(
+            "SELECT note_id, time, outcome, time_spent, scheduler FROM history ORDER BY id")?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let id: String = row.get(0)?;
+            let time: String = row.get(1)?;
+            match timestamp::parse_time(&time) {
+                Ok(time) => histories.entry(id).or_default().push(ReviewEntry {
+                    time,
+                    outcome: enum_from_sql(row.get(2)?)?,
+                    time_spent: row.get(3)?,
+                    scheduler: enum_from_sql(row.get(4)?)?,
+                }),
+                Err(e) => invalid.push(format!("Note {} history: {e}", bold_wrap!(id))),
+            }
+        }
+
+        let mut notes: Vec<Note> = vec![];
+        let mut stmt = self.conn.prepare("SELECT id, name, data FROM notes ORDER BY name")?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let id: String = row.get(0)?;
+            let name: String = row.get(1)?;
+            let data: String = row.get(2)?;
+            let note = NoteId::try_from(id.clone()).map_err(StorageError::Custom)
+                .and_then(|note_id| Ok((note_id, serde_json::from_str::<Note>(&data)?)));
+            match note {
+                Ok((note_id, mut note)) => {
+                    note.id = note_id;
+                    note.name = name;
+                    note.history = histories.remove(&id).unwrap_or_default();
+                    notes.push(note);
+                },
+                Err(e) => invalid.push(format!("Note {}: {e}", bold_wrap!(name))),
+            }
+        }
+
+        if !invalid.is_empty() {
+            return Err(StorageError::InvalidNotes(invalid));
+        }
+        Ok(notes)
+    }
+
+    fn save(&mut self, notes: &[Note]) -> Result<(), StorageError> {
+        let tx = self.conn.savepoint()?;
+        // History is removed along with the notes by the cascade
+        tx.execute("DELETE FROM notes", [])?;
+        for note in notes {
+            insert_note(&tx, note)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn insert_note(&mut self, note: &Note) -> Result<(), StorageError> {
+        let tx = self.conn.savepoint()?;
+        insert_note(&tx, note)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn update_note(&mut self, note: &Note) -> Result<(), StorageError> {
+        let updated = self.conn.execute(
+            "UPDATE notes SET name = ?2, data = ?3 WHERE id = ?1",
+            params![note.id.to_string(), note.name, note_data(note)?])?;
+        if updated == 0 {
+            return Err(StorageError::Custom(format!("No note with id {} is stored", note.id)));
+        }
+        Ok(())
+    }
+
+    fn delete_note(&mut self, id: NoteId) -> Result<(), StorageError> {
+        self.conn.execute("DELETE FROM notes WHERE id = ?1", params![id.to_string()])?;
+        Ok(())
+    }
+
+    fn append_history(&mut self, id: NoteId, entry: &ReviewEntry) -> Result<(), StorageError> {
+        let exists = self.conn.query_row("SELECT 1 FROM notes WHERE id = ?1", params![id.to_string()], |_| Ok(()))
+            .optional()?;
+        if exists.is_none() {
+            return Err(StorageError::Custom(format!("No note with id {id} is stored")));
+        }
+        insert_history(&self.conn, id, entry)
+    }
+
+    fn begin(&mut self) -> Result<(), StorageError> {
+        self.conn.execute_batch("BEGIN")?;
+        Ok(())
+    }
+
+    fn commit(&mut self) -> Result<(), StorageError> {
+        self.conn.execute_batch("COMMIT")?;
+        Ok(())
+    }
+
+    fn rollback(&mut self) -> Result<(), StorageError> {
+        if !self.conn.is_autocommit() {
+            self.conn.execute_batch("ROLLBACK")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,321 @@
+// Storage backends for a collection of notes.
+// The backend is picked from the notes files extension, .db/.sqlite/.sqlite3
+// files use SQLite and everything else is stored as JSON
+use std::path::{Path, PathBuf};
+
+use super::{load_json_data, save_json_data, sqlite::SqliteStore, Note, NoteId, ReviewEntry, StorageError};
+
+// Persistence for a collection of notes.
+// Send so the open store can be saved from the ctrl-c handler
+pub trait NoteStore: Send {
+    // Loads every note, along with its history
+    fn load(&mut self) -> Result<Vec<Note>, StorageError>;
+
+    // Replaces everything stored with the given notes
+    fn save(&mut self, notes: &[Note]) -> Result<(), StorageError>;
+
+    // Adds a new note, along with any history it already has
+    fn insert_note(&mut self, note: &Note) -> Result<(), StorageError>;
+
+    // Saves the values of an existing note, found by its id so renames are saved too.
+    // History isn't touched, it's only added to with append_history
+    fn update_note(&mut self, note: &Note) -> Result<(), StorageError>;
+
+    // Removes a note and its history
+    fn delete_note(&mut self, id: NoteId) -> Result<(), StorageError>;
+
+    // Adds a review to the end of a notes history
+    fn append_history(&mut self, id: NoteId, entry: &ReviewEntry) -> Result<(), StorageError>;
+
+    // Groups the changes that follow so they're written together, ended by commit
+    fn begin(&mut self) -> Result<(), StorageError> {
+        Ok(())
+    }
+
+    // Writes out everything changed since begin
+    fn commit(&mut self) -> Result<(), StorageError> {
+        Ok(())
+    }
+
+    // Throws away everything changed since begin
+    fn rollback(&mut self) -> Result<(), StorageError> {
+        Ok(())
+    }
+}
+
+// Whether the notes file should be stored with SQLite
+pub fn is_sqlite_path(path: &Path) -> bool {
+    matches!(path.extension().and_then(|e| e.to_str()), Some("db" | "sqlite" | "sqlite3"))
+}
+
+// Opens the store for the given notes file, using the backend its extension asks for.
+// Read only stores never write the file, not even to upgrade its format
+pub fn open_store(path: &Path, read_only: bool) -> Result<Box<dyn NoteStore>, StorageError> {
+    if is_sqlite_path(path) {
+        Ok(Box::new(SqliteStore::open(path, read_only)?))
+    } else {
+        Ok(Box::new(JsonStore::new(path, read_only)))
+    }
+}
+
+// Stores notes in a single json file.
+// The whole file is rewritten on every change or batch, which is fine for smaller collections
+pub struct JsonStore {
+    path: PathBuf,
+    // Notes as last loaded or saved, so single note changes don't need to reread the file
+    notes: Option<Vec<Note>>,
+    // Set between begin and commit, changes are only written on commit
+    batching: bool,
+    // Whether there are changes that haven't been written yet
+    pending: bool,
+    read_only: bool,
+}
+
+impl JsonStore {
+    pub fn new(path: &Path, read_only: bool) -> Self {
+        Self { path: path.to_path_buf(), notes: None, batching: false, pending: false, read_only }
+    }
+
+    // Applies a change to the stored notes and writes them back out,
+    // unless the change is part of a batch
+    fn modify<F>(&mut self, change: F) -> Result<(), StorageError>
+    where
+        F: FnOnce(&mut Vec<Note>) -> Result<(), StorageError>
+    {
+        if self.notes.is_none() {
+            self.notes = Some(load_json_data(&self.path, self.read_only)?);
+        }
+        change(self.notes.as_mut().unwrap())?;
+        self.pending = true;
+        if !self.batching {
+            self.write()?;
+        }
+        Ok(())
+    }
+
+    // Writes the cached notes to the file
+    fn write(&mut self) -> Result<(), StorageError> {
+        if let Some(notes) = &self.notes {
+            save_json_data(&self.path, notes.clone())?;
+        }
+        self.pending = false;
+        Ok(())
+    }
+}
+
+impl NoteStore for JsonStore {
+    fn load(&mut self) -> Result<Vec<Note>, StorageError> {
+        let notes = load_json_data(&self.path, self.read_only)?;
+        self.notes = Some(notes.clone());
+        Ok(notes)
+    }
+
+    fn save(&mut self, notes: &[Note]) -> Result<(), StorageError> {
+        save_json_data(&self.path, notes.to_vec())?;
+        self.notes = Some(notes.to_vec());
+        Ok(())
+    }
+
+    fn insert_note(&mut self, note: &Note) -> Result<(), StorageError> {
+        self.modify(|notes| {
+            if notes.iter().any(|n| n.id == note.id) {
+                return Err(StorageError::Custom(format!("A note with id {} is already stored", note.id)));
+            }
+            notes.push(note.clone());
+            Ok(())
+        })
+    }
+
+    fn update_note(&mut self, note: &Note) -> Result<(), StorageError> {
+        self.modify(|notes| {
+            let stored = notes.iter_mut().find(|n| n.id == note.id)
+                .ok_or(StorageError::Custom(format!("No note with id {} is stored", note.id)))?;
+            let history = std::mem::take(&mut stored.history);
+            *stored = note.clone();
+            stored.history = history;
+            Ok(())
+        })
+    }
+
+    fn delete_note(&mut self, id: NoteId) -> Result<(), StorageError> {
+        self.modify(|notes| {
+            notes.retain(|n| n.id != id);
+            Ok(())
+        })
+    }
+
+    fn append_history(&mut self, id: NoteId, entry: &ReviewEntry) -> Result<(), StorageError> {
+        self.modify(|notes| {
+            let stored = notes.iter_mut().find(|n| n.id == id)
+                .ok_or(StorageError::Custom(format!("No note with id {id} is stored")))?;
+            stored.history.push(entry.clone());
+            Ok(())
+        })
+    }
+
+    fn begin(&mut self) -> Result<(), StorageError> {
+        self.batching = true;
+        Ok(())
+    }
+
+    fn commit(&mut self) -> Result<(), StorageError> {
+        self.batching = false;
+        if self.pending {
+            self.write()?;
+        }
+        Ok(())
+    }
+
+    // Drops the cached notes so they're reread from the untouched file
+    fn rollback(&mut self) -> Result<(), StorageError> {
+        self.batching = false;
+        self.pending = false;
+        self.notes = None;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{tests::test_dir, ReviewOutcome};
+    use chrono::{Local, TimeZone};
+    use rusqlite::{params, Connection};
+
+    fn note(name: &str, freq: u16) -> Note {
+        Note::new(name.to_string(), freq, Local.with_ymd_and_hms(2024, 3, 1, 9, 0, 0).unwrap())
+    }
+
+    fn review(day: u32) -> ReviewEntry {
+        ReviewEntry {
+            time: Local.with_ymd_and_hms(2024, 3, day, 9, 0, 0).unwrap(),
+            outcome: Some(ReviewOutcome::Recalled),
+            time_spent: Some(12),
+            scheduler: None,
+        }
+    }
+
+    // Reopens the store so nothing is read back from a cache
+    fn reload(path: &Path) -> Vec<Note> {
+        let mut notes = open_store(path, false).unwrap().load().unwrap();
+        notes.sort_by(|a, b| a.name.cmp(&b.name));
+        notes
+    }
+
+    fn round_trip(path: &Path) {
+        let mut store = open_store(path, false).unwrap();
+        assert!(store.load().unwrap().is_empty());
+        let mut graphs = note("Graphs", 1);
+        graphs.history.push(review(1));
+        let trees = note("Trees", 2);
+        store.insert_note(&graphs).unwrap();
+        store.insert_note(&trees).unwrap();
+        assert!(store.insert_note(&trees).is_err());
+        let loaded = reload(path);
+        assert_eq!(loaded.len(), 2);
+        assert!(!loaded[0].has_changed(&graphs) && !loaded[1].has_changed(&trees));
+
+        // Updates keep the stored history, which is only added to
+        let mut renamed = graphs.clone();
+        renamed.name = "Networks".to_string();
+        renamed.freq = 5;
+        renamed.history.clear();
+        store.update_note(&renamed).unwrap();
+        store.append_history(graphs.id, &review(2)).unwrap();
+        store.delete_note(trees.id).unwrap();
+        assert!(store.update_note(&trees).is_err());
+        assert!(store.append_history(trees.id, &review(2)).is_err());
+        let loaded = reload(path);
+        assert_eq!(loaded.len(), 1);
+        assert_eq!((loaded[0].id, loaded[0].name.as_str(), loaded[0].freq), (graphs.id, "Networks", 5));
+        assert_eq!(loaded[0].history, [review(1), review(2)]);
+
+        // A batch that fails part way leaves nothing behind once rolled back
+        store.begin().unwrap();
+        store.insert_note(&note("Sets", 0)).unwrap();
+        store.append_history(graphs.id, &review(3)).unwrap();
+        assert!(store.update_note(&trees).is_err());
+        store.rollback().unwrap();
+        let loaded = reload(path);
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(loaded[0].history.len(), 2);
+        assert_eq!(store.load().unwrap().len(), 1);
+
+        store.begin().unwrap();
+        store.insert_note(&note("Sets", 0)).unwrap();
+        store.commit().unwrap();
+        assert_eq!(reload(path).len(), 2);
+    }
+
+    #[test]
+    fn json_store_round_trips() {
+        let dir = test_dir("json_round_trip");
+        round_trip(&dir.join("notes.json"));
+    }
+
+    #[test]
+    fn sqlite_store_round_trips() {
+        let dir = test_dir("sqlite_round_trip");
+        round_trip(&dir.join("notes.db"));
+    }
+
+    #[test]
+    fn read_only_sqlite_stores_arent_created() {
+        let dir = test_dir("sqlite_read_only");
+        let path = dir.join("missing").join("notes.db");
+        assert!(open_store(&path, true).is_err());
+        assert!(!dir.join("missing").exists());
+
+        let path = dir.join("notes.db");
+        open_store(&path, false).unwrap().insert_note(&note("Graphs", 1)).unwrap();
+        let mut store = open_store(&path, true).unwrap();
+        assert_eq!(store.load().unwrap().len(), 1);
+        assert!(store.insert_note(&note("Trees", 1)).is_err());
+    }
+
+    #[test]
+    fn sqlite_v1_databases_are_migrated() {
+        let dir = test_dir("sqlite_v1");
+        let path = dir.join("notes.db");
+        {
+            let conn = Connection::open(&path).unwrap();
+            conn.execute_batch("
+                CREATE TABLE notes (name TEXT PRIMARY KEY, data TEXT NOT NULL);
+                CREATE TABLE history (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    note_name TEXT NOT NULL REFERENCES notes(name) ON DELETE CASCADE ON UPDATE CASCADE,
+                    time TEXT NOT NULL,
+                    outcome TEXT,
+                    time_spent INTEGER,
+                    scheduler TEXT
+                );
+                CREATE INDEX history_note ON history(note_name);
+                PRAGMA user_version = 1;
+            ").unwrap();
+            for (name, reviews) in [("Graphs", 2), ("Trees", 0)] {
+                // Version 1 notes had no ids
+                let mut data = serde_json::to_value(note(name, 3)).unwrap();
+                data.as_object_mut().unwrap().remove("id");
+                data.as_object_mut().unwrap().remove("history");
+                conn.execute("INSERT INTO notes (name, data) VALUES (?1, ?2)", params![name, data.to_string()]).unwrap();
+                for day in 1..=reviews {
+                    conn.execute("INSERT INTO history (note_name, time, outcome, time_spent) VALUES (?1, ?2, 'Recalled', 12)",
+                        params![name, review(day).time.to_rfc3339()]).unwrap();
+                }
+            }
+        }
+        // Upgrading needs to write, so read only stores refuse older schemas
+        assert!(open_store(&path, true).is_err());
+
+        let notes = reload(&path);
+        assert_eq!(notes.iter().map(|n| n.name.as_str()).collect::<Vec<_>>(), ["Graphs", "Trees"]);
+        assert_ne!(notes[0].id, notes[1].id);
+        assert_eq!(notes[0].history, [review(1), review(2)]);
+        assert!(notes[1].history.is_empty());
+        let conn = Connection::open(&path).unwrap();
+        assert_eq!(conn.query_row("PRAGMA user_version", [], |row| row.get::<_, i64>(0)).unwrap(), 2);
+        // Ids stay put once given
+        let reloaded = reload(&path);
+        assert_eq!((reloaded[0].id, reloaded[1].id), (notes[0].id, notes[1].id));
+    }
+}
@@ -0,0 +1,44 @@
+// Serde helpers for saved times.
+// Times are written as RFC 3339, but older saves used Local::now().to_string(),
+// so both are accepted when reading
+use chrono::{DateTime, Local};
+use serde::{de, Deserialize, Deserializer, Serializer};
+
+// Format used by Local::now().to_string(), which older saves were written in
+pub const LEGACY_TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.9f %z";
+
+// Parses an RFC 3339 or legacy time, with an error naming the bad value
+pub fn parse_time(time: &str) -> Result<DateTime<Local>, String> {
+    DateTime::parse_from_rfc3339(time)
+        .or_else(|_| DateTime::parse_from_str(time, LEGACY_TIME_FORMAT))
+        .map(|t| t.with_timezone(&Local))
+        .map_err(|_| format!(
+            "invalid timestamp \"{time}\", expected RFC 3339 such as \"2024-09-01T18:30:00+01:00\""))
+}
+
+pub fn serialize<S: Serializer>(time: &DateTime<Local>, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&time.to_rfc3339())
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<DateTime<Local>, D::Error> {
+    let time = String::deserialize(deserializer)?;
+    parse_time(&time).map_err(de::Error::custom)
+}
+
+// Same as above, for times that may not have been set yet
+pub mod option {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(time: &Option<DateTime<Local>>, serializer: S) -> Result<S::Ok, S::Error> {
+        match time {
+            Some(time) => serializer.serialize_some(&time.to_rfc3339()),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<DateTime<Local>>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|time| parse_time(&time).map_err(de::Error::custom))
+            .transpose()
+    }
+}
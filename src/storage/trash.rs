@@ -0,0 +1,72 @@
+// Removed notes, kept with all of their review data and history so they
+// can be restored. Notes are purged for good by hand, or once they've
+// been in the trash longer than the collections trash_days setting
+use std::{fs, path::PathBuf};
+use chrono::{DateTime, Local, TimeDelta};
+use serde::{Deserialize, Serialize};
+
+use super::{get_store_path, timestamp, write_atomic, Note, StorageError};
+
+// A removed note and when it was removed
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct TrashedNote {
+    pub note: Note,
+    #[serde(with = "timestamp")]
+    pub removed: DateTime<Local>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Trash {
+    // Oldest first
+    pub notes: Vec<TrashedNote>,
+}
+
+impl Trash {
+    pub fn add(&mut self, note: Note) {
+        self.notes.push(TrashedNote { note, removed: Local::now() });
+    }
+
+    // Takes out the trashed copy of the given note, if it's exactly the same
+    pub fn take_matching(&mut self, note: &Note) -> Option<TrashedNote> {
+        let index = self.notes.iter().rposition(|trashed| !trashed.note.has_changed(note))?;
+        Some(self.notes.remove(index))
+    }
+
+    // Purges notes trashed more than the given number of days ago,
+    // returning the purged notes. 0 days keeps them forever
+    pub fn purge_older_than(&mut self, days: u32) -> Vec<TrashedNote> {
+        if days == 0 {
+            return vec![];
+        }
+        let cutoff = Local::now() - TimeDelta::days(days.into());
+        let (kept, purged) = std::mem::take(&mut self.notes).into_iter()
+            .partition(|trashed| trashed.removed > cutoff);
+        self.notes = kept;
+        purged
+    }
+}
+
+// Gets the path of the trash for the current notes file
+pub fn get_trash_path() -> PathBuf {
+    get_store_path().with_file_name("trash.json")
+}
+
+// Loads the trash, empty if nothing has been removed yet
+pub fn load_trash() -> Result<Trash, StorageError> {
+    let trash_path = get_trash_path();
+    if !trash_path.exists() {
+        return Ok(Trash::default());
+    }
+    let file = fs::read_to_string(trash_path)?;
+    if file.trim().is_empty() {
+        Ok(Trash::default())
+    } else {
+        Ok(serde_json::from_str(&file)?)
+    }
+}
+
+pub fn save_trash(trash: &Trash) -> Result<(), StorageError> {
+    let json_string = serde_json::to_string_pretty(trash)?;
+    write_atomic(&get_trash_path(), json_string.as_bytes())
+}
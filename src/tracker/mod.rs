@@ -1,10 +1,18 @@
 use crate::storage::*;
-use std::collections::HashMap;
+use std::{collections::{HashMap, HashSet}, sync::Mutex, time::Duration};
 use thiserror::Error;
 use itertools::Itertools;
 use chrono::prelude::*;
 
 use lazy_static::lazy_static;
+
+pub mod scheduler;
+pub mod note_map;
+pub mod tags;
+pub mod sync;
+use scheduler::ReviewSection;
+pub use note_map::{clean_name, name_key, scoped_name, NoteMap, SCOPE_SEPARATOR};
+
 // Instantiated static during runtime
 lazy_static! {
     // List of FG codes for ASCII
@@ -92,114 +100,437 @@ pub enum TrackerError{
     #[error("{0}")]
     DateTime(#[from] chrono::ParseError),
 
+    #[error("Notes were opened read only, so changes can't be saved")]
+    ReadOnly,
+
+    #[error("A note named {} already exists", bold_wrap!(.0))]
+    NameConflict(String),
+
+    #[error("{0}")]
+    InvalidName(String),
+
+    #[error("Invalid tag expression, {0}")]
+    InvalidTagFilter(String),
+
     #[error("There was an unexpected error: {0}")]
     Custom(String)
 }
 
-// Creates a hashmap of all notes
-// from the saved json file
-pub fn load_map() -> Result<HashMap<String, Note>, TrackerError>{
-    // Loads data from storage
-    let note_data: Vec<Note> = load_json_data()?;
-    // Creates map with note names as keys
-    let map: HashMap<String, Note> = note_data.into_iter()
-    .map(|note| (note.name.clone(), note)).collect();
-    Ok(map)
+// Held while notes are being written, so an interrupt
+// waits for the write to finish before exiting
+pub static FLUSH_LOCK: Mutex<()> = Mutex::new(());
+
+// The open notes store. The map keeps the saved copy of every note changed
+// through it, so only notes that actually changed are written,
+// rather than saving and reloading the whole map.
+// The store stays locked while open, unless it was opened read only.
+// Every flush is journaled as one operation so it can be undone,
+// and notes it removes are moved to the trash
+pub struct MapStore {
+    store: Box<dyn store::NoteStore>,
+    journal: journal::Journal,
+    trash: trash::Trash,
+    _lock: Option<lock::StoreLock>,
+    read_only: bool,
 }
 
-// Saves the current map
-// to json file
-pub fn save_map(map: HashMap<String, Note>) -> Result<(), TrackerError>{
-    let note_data: Vec<Note> = map.into_iter().map(|note| note.1).collect();
-    save_json_data(note_data)?;
-    Ok(())
+impl MapStore {
+    // Opens the current notes store, returning it with its notes as a map.
+    // Errors with StorageError::Locked if it's open in another tracker, unless opened read only
+    pub fn open(read_only: bool) -> Result<(Self, NoteMap), TrackerError> {
+        let path = get_store_path();
+        let lock = match read_only {
+            true => None,
+            false => Some(lock::StoreLock::acquire(&path)?),
+        };
+        let mut store = store::open_store(&path, read_only)?;
+        let notes = store.load()?;
+        // Tidied names count as changes, so they're saved below
+        let (mut map, clashes) = NoteMap::from_notes(notes);
+        let journal = journal::load_journal()?;
+        let trash = trash::load_trash()?;
+        let mut map_store = Self { store, journal, trash, _lock: lock, read_only };
+
+        // Older collections could have names differing only by case or spacing,
+        // which are now the same name, so the later ones are renamed apart
+        for mut note in clashes {
+            let name = format!("{} ({})", note.name.trim(), note.id);
+            println!("{} Another note is already named {}, so it was renamed to {}",
+                red_wrap!("Warning:"), bold_wrap!(note.name), bold_wrap!(name));
+            note.name = name;
+            map.insert(note)?;
+        }
+        // Saves any names that were tidied or renamed.
+        // Not journaled, as undoing it would bring the clashes back
+        if !read_only {
+            map_store.write(&mut map)?;
+        }
+        Ok((map_store, map))
+    }
+
+    // Opens the current notes store again, e.g. after switching profile or restoring a backup.
+    // The old lock is let go first, so reopening the same notes doesn't lock against itself
+    pub fn reopen(&mut self) -> Result<NoteMap, TrackerError> {
+        self._lock = None;
+        let (store, map) = Self::open(self.read_only)?;
+        *self = store;
+        Ok(map)
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    // Whether the map has changes that haven't been saved
+    pub fn is_dirty(&self, note_map: &NoteMap) -> bool {
+        note_map.has_changes()
+    }
+
+    // Writes every note added, changed or removed since the last flush,
+    // journaling them under the given action. Returns how many were written
+    pub fn flush(&mut self, note_map: &mut NoteMap, action: &str) -> Result<usize, TrackerError> {
+        let changes: Vec<journal::NoteChange> = note_map.changes()
+            .map(|(before, after)| journal::NoteChange { before: before.cloned(), after: after.cloned() })
+            .collect();
+        let written = self.write(note_map)?;
+        if written > 0 {
+            self.journal.record(action, changes);
+            journal::save_journal(&self.journal)?;
+        }
+        Ok(written)
+    }
+
+    // Undoes the last journaled operation, returning it if there was one
+    pub fn undo(&mut self, note_map: &mut NoteMap) -> Result<Option<journal::Operation>, TrackerError> {
+        self.step(note_map, true)
+    }
+
+    // Redoes the last undone operation, returning it if there was one
+    pub fn redo(&mut self, note_map: &mut NoteMap) -> Result<Option<journal::Operation>, TrackerError> {
+        self.step(note_map, false)
+    }
+
+    // Actions that can be undone and redone, most recent first
+    pub fn history(&self) -> (Vec<&journal::Operation>, Vec<&journal::Operation>) {
+        (self.journal.undo.iter().rev().collect(), self.journal.redo.iter().rev().collect())
+    }
+
+    // Forgets everything journaled, for when the notes are replaced wholesale
+    pub fn clear_journal(&mut self) -> Result<(), TrackerError> {
+        self.journal = journal::Journal::default();
+        journal::save_journal(&self.journal)?;
+        Ok(())
+    }
+
+    // Notes in the trash, oldest first
+    pub fn trashed(&self) -> &[trash::TrashedNote] {
+        &self.trash.notes
+    }
+
+    // Puts a trashed note back in the map, optionally under a new name.
+    // Errors if a note with the same name is already in the map
+    pub fn restore_trashed(&mut self, note_map: &mut NoteMap, index: usize, name: Option<String>) -> Result<String, TrackerError> {
+        if self.read_only {
+            return Err(TrackerError::ReadOnly);
+        }
+        let mut note = self.trash.notes.get(index)
+            .ok_or(TrackerError::Custom(format!("No trashed note at position {index}")))?
+            .note.clone();
+        if let Some(name) = name {
+            note.name = name;
+        }
+        if note_map.contains_name(&note.name) {
+            return Err(TrackerError::NameConflict(note.name));
+        }
+        // Trashed notes keep their id, but it may have been given to another note since
+        if note_map.contains(&note.id) {
+            note.id = NoteId::new();
+        }
+
+        let trashed = self.trash.notes.remove(index);
+        let id = note_map.insert(note)?;
+        let name = note_map.get(&id).map(|note| note.name.clone()).unwrap_or_default();
+        if let Err(e) = self.flush(note_map, "Restore from Trash") {
+            note_map.remove(&id);
+            self.trash.notes.insert(index, trashed);
+            return Err(e);
+        }
+        trash::save_trash(&self.trash)?;
+        Ok(name)
+    }
+
+    // Permanently deletes the trashed notes at the given positions
+    pub fn purge_trashed(&mut self, indices: &[usize]) -> Result<usize, TrackerError> {
+        if self.read_only {
+            return Err(TrackerError::ReadOnly);
+        }
+        let mut indices = indices.to_vec();
+        indices.sort_unstable();
+        indices.dedup();
+        indices.retain(|index| *index < self.trash.notes.len());
+        // Removed from the back so the earlier positions stay put
+        let purged = indices.iter().rev().map(|index| self.trash.notes.remove(*index)).collect_vec();
+        self.forget_purged(&purged)?;
+        Ok(purged.len())
+    }
+
+    // Permanently deletes notes trashed more than the given number of days ago
+    pub fn purge_old_trash(&mut self, days: u32) -> Result<usize, TrackerError> {
+        if self.read_only {
+            return Ok(0);
+        }
+        let purged = self.trash.purge_older_than(days);
+        if !purged.is_empty() {
+            self.forget_purged(&purged)?;
+        }
+        Ok(purged.len())
+    }
+
+    // Saves the trash once notes are purged from it, and takes them out of the journal
+    // so undoing their removal can't bring them back. Notes with another copy still in the trash are kept
+    fn forget_purged(&mut self, purged: &[trash::TrashedNote]) -> Result<(), TrackerError> {
+        trash::save_trash(&self.trash)?;
+        let ids: HashSet<NoteId> = purged.iter()
+            .map(|trashed| trashed.note.id)
+            .filter(|id| !self.trash.notes.iter().any(|trashed| trashed.note.id == *id))
+            .collect();
+        if self.journal.forget(&ids) {
+            journal::save_journal(&self.journal)?;
+        }
+        Ok(())
+    }
+
+    // Moves one operation between the undo and redo lists, applying it to the map
+    fn step(&mut self, note_map: &mut NoteMap, undo: bool) -> Result<Option<journal::Operation>, TrackerError> {
+        if self.read_only {
+            return Err(TrackerError::ReadOnly);
+        }
+        // Anything not yet saved is its own step, so it isn't mixed into the one being undone
+        self.flush(note_map, "Unsaved changes")?;
+        let operation = match undo {
+            true => self.journal.undo.pop(),
+            false => self.journal.redo.pop(),
+        };
+        let Some(operation) = operation else {
+            return Ok(None);
+        };
+
+        apply_changes(note_map, &operation.changes, undo);
+        if let Err(e) = self.write(note_map) {
+            // Put everything back how it was, so the journal still matches the notes
+            apply_changes(note_map, &operation.changes, !undo);
+            match undo {
+                true => self.journal.undo.push(operation),
+                false => self.journal.redo.push(operation),
+            }
+            return Err(e);
+        }
+        match undo {
+            true => self.journal.redo.push(operation.clone()),
+            false => self.journal.undo.push(operation.clone()),
+        }
+        journal::save_journal(&self.journal)?;
+        Ok(Some(operation))
+    }
+
+    // Writes the map to the store without journaling it
+    fn write(&mut self, note_map: &mut NoteMap) -> Result<usize, TrackerError> {
+        if !self.is_dirty(note_map) {
+            return Ok(0);
+        }
+        if self.read_only {
+            return Err(TrackerError::ReadOnly);
+        }
+        let _lock = FLUSH_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        self.store.begin()?;
+        // A failed commit is rolled back too, so the store doesn't hold on to changes that were never written
+        let written = self.write_changes(note_map).and_then(|written| {
+            self.store.commit()?;
+            Ok(written)
+        });
+        match written {
+            Ok(written) => {
+                self.update_trash(note_map)?;
+                note_map.mark_saved();
+                Ok(written)
+            },
+            Err(e) => {
+                self.store.rollback()?;
+                Err(e)
+            },
+        }
+    }
+
+    // Moves notes that were removed from the map into the trash.
+    // Notes put back exactly as they were, e.g. by an undo, are taken back out
+    fn update_trash(&mut self, note_map: &NoteMap) -> Result<(), TrackerError> {
+        let mut changed = false;
+        for change in note_map.changes() {
+            match change {
+                (Some(saved), None) => self.trash.add(saved.clone()),
+                (None, Some(note)) if self.trash.take_matching(note).is_some() => {},
+                _ => continue,
+            }
+            changed = true;
+        }
+        if changed {
+            trash::save_trash(&self.trash)?;
+        }
+        Ok(())
+    }
+
+    fn write_changes(&mut self, note_map: &NoteMap) -> Result<usize, TrackerError> {
+        // Removed notes go first, so they're out of the way of anything added
+        let changes = note_map.changes().sorted_by_key(|(_, note)| note.is_some()).collect_vec();
+        for (saved, note) in &changes {
+            match (saved, note) {
+                (Some(saved), None) => self.store.delete_note(saved.id)?,
+                (None, Some(note)) => self.store.insert_note(note)?,
+                // Reviews only add to the end of the history, so just the new entries are appended
+                (Some(saved), Some(note)) if note.history.starts_with(&saved.history) => {
+                    self.store.update_note(note)?;
+                    for entry in &note.history[saved.history.len()..] {
+                        self.store.append_history(note.id, entry)?;
+                    }
+                },
+                // History was rewritten, so the note is stored again from scratch
+                (Some(saved), Some(note)) => {
+                    self.store.delete_note(saved.id)?;
+                    self.store.insert_note(note)?;
+                },
+                (None, None) => {},
+            }
+        }
+        Ok(changes.len())
+    }
+}
+
+// Puts each changed note back how it was before the operation when undoing,
+// or how it was after when redoing. Every note is taken out before any are
+// put back, so renames can't clash with each other
+fn apply_changes(note_map: &mut NoteMap, changes: &[journal::NoteChange], undo: bool) {
+    let (from, to): (Vec<_>, Vec<_>) = changes.iter()
+        .map(|change| match undo {
+            true => (&change.after, &change.before),
+            false => (&change.before, &change.after),
+        })
+        .unzip();
+    for note in from.into_iter().flatten() {
+        note_map.remove(&note.id);
+    }
+    for note in to.into_iter().flatten() {
+        // Can't clash, as the journal puts back exactly what was there
+        let _ = note_map.insert(note.clone());
+    }
 }
 
 // Prints out the hash map of notes in an arbitary order
-pub fn view_map(map: &HashMap<String, Note>) {
-    for key in map.keys().sorted(){
+pub fn view_map(map: &NoteMap) {
+    for note in map.values().sorted_by(|a, b| a.name.cmp(&b.name)){
         println!("{} has been reviewed {} times. Last reviewed, {},\n{} ago.",
-                    bold_wrap!(map[key].name),
-                    bold_wrap!(map[key].freq),
-                    bold_wrap!(format_time_for_output(&map[key].last_accessed)),
-                    format_time_since(&map[key].last_accessed).unwrap()
+                    bold_wrap!(note.name),
+                    bold_wrap!(note.freq),
+                    bold_wrap!(format_time_for_output(&note.last_accessed)),
+                    format_time_since(&note.last_accessed).unwrap_or_else(|e| e.to_string())
         );
     }
     println!("\n");
 }
 
 
-// Updates the note values map within map
-pub fn update_reviewed_notes(note_map: &mut HashMap<String, Note>, reviewed: Vec<Note>) {
-    for note in note_map.values_mut() {
-        if reviewed.iter().any(|v| v == note) {
-            note.freq += 1;
-            note.last_accessed = Local::now().to_string();
+// A note graded by the user during a review
+pub struct GradedNote {
+    pub note: Note,
+    // Index of the grade picked from the schedulers grades
+    pub grade: usize,
+    pub time_spent: Duration,
+}
+
+// Updates the note values within map, letting the collections scheduler
+// apply the grade given to each reviewed note and logging the review.
+// Notes that were skipped shouldn't be passed in.
+// Returns how many parent notes had reviews rolled up to them
+pub fn update_reviewed_notes(note_map: &mut NoteMap, settings: &Settings, reviewed: Vec<GradedNote>) -> usize {
+    let scheduler = settings.scheduler.scheduler(settings);
+    let now = Local::now();
+    for graded in &reviewed {
+        if let Some(note) = note_map.get_mut(&graded.note.id) {
+            scheduler.review_note(note, graded.grade, now);
+            note.history.push(ReviewEntry {
+                time: note.last_accessed,
+                outcome: Some(scheduler.outcome(graded.grade)),
+                time_spent: Some(graded.time_spent.as_secs()),
+                scheduler: Some(settings.scheduler),
+            });
         }
     }
-}
+    if !settings.roll_up_reviews {
+        return 0;
+    }
 
-#[allow(unused)]
-pub fn manual_note_update(note_map: HashMap<String, Note>, freq: u16, last_accessed: String) { 
-    std::todo!("Write method")
+    // Each parent takes the worst grade given to the notes under it,
+    // unless it was reviewed itself
+    let mut parents: HashMap<NoteId, usize> = HashMap::new();
+    for graded in &reviewed {
+        let Some(parent) = graded.note.parent else {
+            continue;
+        };
+        if reviewed.iter().any(|g| g.note.id == parent) {
+            continue;
+        }
+        let grade = parents.entry(parent).or_insert(graded.grade);
+        if scheduler.outcome(graded.grade) > scheduler.outcome(*grade) {
+            *grade = graded.grade;
+        }
+    }
+    let mut rolled_up = 0;
+    for (id, grade) in parents {
+        if let Some(note) = note_map.get_mut(&id) {
+            scheduler.review_note(note, grade, now);
+            note.history.push(ReviewEntry {
+                time: note.last_accessed,
+                outcome: Some(scheduler.outcome(grade)),
+                time_spent: None,
+                scheduler: Some(settings.scheduler),
+            });
+            rolled_up += 1;
+        }
+    }
+    rolled_up
 }
 
-
-// Review Calculations \\
-
-// Sorts notes by which ones have been reviewed the least and which ones are the oldest
-pub fn get_notes_to_review(note_map: &HashMap<String, Note>) -> (Vec<Note>, Vec<Note>) {
-    let mut notes: Vec<&Note> = note_map.values().collect();
-
-    // Sort by freq
-    notes.sort_by(|a, b| a.freq.cmp(&b.freq));
-    // Gets 3 most uncommon 
-    let uncommon = notes.iter().take(3).cloned().cloned().collect_vec();    
-    
-    // Sort by date
-    notes.sort_by(|a, b| {
-        let a_time = DateTime::parse_from_str(
-            &a.last_accessed,
-            "%Y-%m-%d %H:%M:%S%.9f %z").unwrap();
-        let b_time = DateTime::parse_from_str(
-            &b.last_accessed,
-            "%Y-%m-%d %H:%M:%S%.9f %z").unwrap();
-        a_time.cmp(&b_time)
+// Manually sets a notes review values, logging the change in its history
+pub fn manual_note_update(note: &mut Note, freq: u16, last_accessed: DateTime<Local>) {
+    note.freq = freq;
+    note.last_accessed = last_accessed;
+    note.history.push(ReviewEntry {
+        time: Local::now(),
+        outcome: None,
+        time_spent: None,
+        scheduler: None,
     });
-    // Gets 2 oldest that arn't already in the most uncommon vec
-    let olderst = notes.iter()
-        .filter(|n| !uncommon.contains(n))
-        .take(2).cloned().cloned().collect_vec();
-
-    (uncommon, olderst)
 }
 
 
-// Formats the review text
-pub fn format_review(uncommon: &Vec<Note>, oldest: &Vec<Note>) {
+// Review Formatting \\
+
+// Formats the review text, printing each section the scheduler picked
+pub fn format_review(scheduler_name: &str, sections: &[ReviewSection]) {
     // Title
     println!("{}\n",bold_wrap!("...Notes to Review..."));
-    
-    // Least common    
-    println!("{}", bold_wrap!("Least Reviewed:"));
-    for note in uncommon {     
-        println!("Note: {} - Reviewed {} times, last at {}",
-            bold_wrap!(note.name),
-            bold_wrap!(note.freq),
-            bold_wrap!(format_time_for_output(&note.last_accessed))
-        );
-    }
-
-    // Oldest
-    println!("\n{}",bold_wrap!("Oldest Since Last Review:"));
-
-    for note in oldest {        
-        println!("Note: {} - Last reviewed at: {} times, Last at {}\n\tTime since review: {}",
-            bold_wrap!(note.name),
-            bold_wrap!(note.freq),
-            bold_wrap!(format_time_for_output(&note.last_accessed)),
-            bold_wrap!(format_time_since(&note.last_accessed).unwrap())
-        );
+    println!("Scheduler: {}\n", bold_wrap!(scheduler_name));
+
+    for section in sections {
+        println!("{}", bold_wrap!(section.title));
+        for note in &section.notes {
+            println!("Note: {} - Reviewed {} times, last at {}\n\tTime since review: {}",
+                bold_wrap!(note.name),
+                bold_wrap!(note.freq),
+                bold_wrap!(format_time_for_output(&note.last_accessed)),
+                bold_wrap!(format_time_since(&note.last_accessed).unwrap_or_else(|e| e.to_string()))
+            );
+        }
+        println!();
     }
 
     // Create gap between next select
@@ -225,13 +556,9 @@ fn calculate_time_difference(dt1: DateTime<Utc>, dt2: DateTime<Utc>) -> Result<H
 }
 
 
-// Converts the UTC time into a format more human readable
-pub fn format_time_for_output(time: &str) -> String {    
-    let utc_time = DateTime::parse_from_str(
-        time,
-        "%Y-%m-%d %H:%M:%S%.9f %z").unwrap();
-    
-    format!("{}", utc_time.format("%Y-%m-%d %H:%M:%S"))
+// Converts the time into a format more human readable
+pub fn format_time_for_output(time: &DateTime<Local>) -> String {    
+    format!("{}", time.format("%Y-%m-%d %H:%M:%S"))
 }
 
 // Formats the output time into 
@@ -239,8 +566,8 @@ pub fn format_time_for_output(time: &str) -> String {
 // compared to UTC::now()
 // Shows the largest time value, so
 // 70 seconds == 1 minutes 10 seocnds
-pub fn format_time_since(time: &str) -> Result<String, TrackerError> {    
-    let date: DateTime<Utc> = DateTime::parse_from_str(time, "%Y-%m-%d %H:%M:%S%.9f %z")?.into();
+pub fn format_time_since(time: &DateTime<Local>) -> Result<String, TrackerError> {    
+    let date: DateTime<Utc> = time.to_utc();
     
     match calculate_time_difference(Utc::now(), date) {
         Ok(mut map) => {
@@ -289,3 +616,115 @@ pub fn format_time_since(time: &str) -> Result<String, TrackerError> {
         Err(e) => Err(TrackerError::DateTimeFormatting(format!("{e}"))),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::tests::test_dir;
+
+    // The store is found through the global store path, so tests opening one take turns
+    static STORE_PATH_LOCK: Mutex<()> = Mutex::new(());
+
+    fn note(name: &str) -> Note {
+        Note::new(name.to_string(), 0, Local::now())
+    }
+
+    fn names(note_map: &NoteMap) -> Vec<String> {
+        note_map.values().map(|note| note.name.clone()).sorted().collect()
+    }
+
+    #[test]
+    fn undo_and_redo_round_trip() {
+        let _turn = STORE_PATH_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        set_store_path(test_dir("undo_redo").join("notes.json"));
+        let (mut map_store, mut note_map) = MapStore::open(false).unwrap();
+        let graphs = note_map.insert(note("Graphs")).unwrap();
+        map_store.flush(&mut note_map, "Add Note").unwrap();
+        note_map.rename(&graphs, "Networks").unwrap();
+        note_map.get_mut(&graphs).unwrap().freq = 3;
+        note_map.insert(note("Trees")).unwrap();
+        map_store.flush(&mut note_map, "Edit Note").unwrap();
+
+        let undone = map_store.undo(&mut note_map).unwrap().unwrap();
+        assert_eq!((undone.action.as_str(), undone.changes.len()), ("Edit Note", 2));
+        assert_eq!(names(&note_map), ["Graphs"]);
+        assert_eq!(note_map.get(&graphs).unwrap().freq, 0);
+        // Undoing an added note trashes it, until it's redone
+        assert_eq!(map_store.trashed().len(), 1);
+
+        // Undo is saved, along with the journal
+        drop(map_store);
+        let (mut map_store, mut note_map) = MapStore::open(false).unwrap();
+        assert_eq!(names(&note_map), ["Graphs"]);
+        assert_eq!(map_store.history().1.len(), 1);
+        assert_eq!(map_store.redo(&mut note_map).unwrap().unwrap().action, "Edit Note");
+        assert_eq!(names(&note_map), ["Networks", "Trees"]);
+        assert_eq!(note_map.get(&graphs).unwrap().freq, 3);
+        assert!(map_store.trashed().is_empty());
+        assert!(map_store.redo(&mut note_map).unwrap().is_none());
+
+        // A new action drops everything that could be redone
+        map_store.undo(&mut note_map).unwrap();
+        note_map.insert(note("Sets")).unwrap();
+        map_store.flush(&mut note_map, "Add Note").unwrap();
+        assert!(map_store.history().1.is_empty());
+        assert!(map_store.redo(&mut note_map).unwrap().is_none());
+        assert_eq!(names(&note_map), ["Graphs", "Sets"]);
+
+        map_store.undo(&mut note_map).unwrap();
+        map_store.undo(&mut note_map).unwrap();
+        assert!(note_map.is_empty());
+        assert!(map_store.undo(&mut note_map).unwrap().is_none());
+        drop(map_store);
+        let (map_store, note_map) = MapStore::open(false).unwrap();
+        assert!(note_map.is_empty());
+        assert_eq!(map_store.history().1.len(), 2);
+    }
+
+    #[test]
+    fn unsaved_changes_are_undone_on_their_own() {
+        let _turn = STORE_PATH_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        set_store_path(test_dir("undo_unsaved").join("notes.json"));
+        let (mut map_store, mut note_map) = MapStore::open(false).unwrap();
+        let graphs = note_map.insert(note("Graphs")).unwrap();
+        map_store.flush(&mut note_map, "Add Note").unwrap();
+        note_map.get_mut(&graphs).unwrap().freq = 2;
+
+        assert_eq!(map_store.undo(&mut note_map).unwrap().unwrap().action, "Unsaved changes");
+        assert_eq!(note_map.get(&graphs).unwrap().freq, 0);
+        assert_eq!(map_store.undo(&mut note_map).unwrap().unwrap().action, "Add Note");
+        assert!(note_map.is_empty());
+    }
+
+    #[test]
+    fn purged_notes_cant_be_undone_back() {
+        let _turn = STORE_PATH_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        set_store_path(test_dir("undo_purged").join("notes.json"));
+        let (mut map_store, mut note_map) = MapStore::open(false).unwrap();
+        for name in ["Graphs", "Trees", "Sets"] {
+            note_map.insert(note(name)).unwrap();
+        }
+        map_store.flush(&mut note_map, "Add Note").unwrap();
+        note_map.remove_by_name("Graphs");
+        map_store.flush(&mut note_map, "Remove Note").unwrap();
+        note_map.remove_by_name("Trees");
+        map_store.flush(&mut note_map, "Remove Note").unwrap();
+
+        assert_eq!(map_store.purge_trashed(&[0]).unwrap(), 1);
+        // Trashed long enough ago to be purged by age
+        map_store.trash.notes[0].removed = Local::now() - chrono::TimeDelta::days(40);
+        assert_eq!(map_store.purge_old_trash(30).unwrap(), 1);
+        assert!(map_store.trashed().is_empty());
+
+        // Both removals only changed purged notes, so they're gone from the journal
+        drop(map_store);
+        let (mut map_store, mut note_map) = MapStore::open(false).unwrap();
+        let (undo, _) = map_store.history();
+        assert_eq!(undo.iter().map(|operation| operation.changes.len()).collect_vec(), [1]);
+        assert_eq!(map_store.undo(&mut note_map).unwrap().unwrap().action, "Add Note");
+        assert!(note_map.is_empty());
+        assert!(map_store.undo(&mut note_map).unwrap().is_none());
+        map_store.redo(&mut note_map).unwrap();
+        assert_eq!(names(&note_map), ["Sets"]);
+    }
+}
@@ -0,0 +1,391 @@
+// Every note in the collection, keyed by its stable id.
+// Names are unique labels that can change, so they're looked up through
+// an index rather than used as keys.
+// Every name goes through one policy: trimmed and unicode normalized, then
+// compared ignoring case and compatibility forms, so "Graphs", " graphs "
+// and "ｇｒａｐｈｓ" are all the same name.
+// The map keeps the saved copy of each note changed through it, so only
+// those notes need comparing and writing when it's next saved
+use std::collections::{hash_map, HashMap, HashSet};
+use itertools::Itertools;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::storage::{Note, NoteId};
+use super::TrackerError;
+
+#[derive(Debug, Clone, Default)]
+pub struct NoteMap {
+    notes: HashMap<NoteId, Note>,
+    // Lowercased name of every note to its id
+    names: HashMap<String, NoteId>,
+    // Each note added, changed or removed since the map was last saved,
+    // as it was when saved. None for notes that weren't saved at all
+    changed: HashMap<NoteId, Option<Note>>,
+}
+
+// Tidies a name as it's saved, erroring if nothing is left
+pub fn clean_name(name: &str) -> Result<String, TrackerError> {
+    let name: String = name.trim().nfc().collect();
+    if name.is_empty() {
+        return Err(TrackerError::InvalidName("Note names can't be empty".to_string()));
+    }
+    Ok(name)
+}
+
+// Key a name is indexed under, names with the same key are the same name
+pub fn name_key(name: &str) -> String {
+    name.trim().nfkc().collect::<String>().to_lowercase()
+}
+
+// Goes between a header and the note it sits under in the names of imported notes
+pub const SCOPE_SEPARATOR: &str = " › ";
+
+// Name for a note imported from a header, scoped to the note it sits under so the same header
+// in different files or sections gets its own note, e.g. "graphs › BFS › Examples".
+// Headers with nothing above them just get the prefix
+pub fn scoped_name(prefix: &str, parent_name: Option<&str>, text: &str) -> String {
+    match parent_name {
+        Some(parent_name) => format!("{parent_name}{SCOPE_SEPARATOR}{text}"),
+        None => format!("{prefix}{text}"),
+    }
+}
+
+impl NoteMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Builds the map from loaded notes, tidying their names. Notes whose names
+    // are empty or clash with one already added are returned rather than dropped,
+    // so they can be renamed apart.
+    // The notes count as saved, apart from tidied names and the clashes left out
+    pub fn from_notes(notes: Vec<Note>) -> (Self, Vec<Note>) {
+        let mut map = Self::new();
+        let mut clashes: Vec<Note> = vec![];
+        for mut note in notes {
+            match clean_name(&note.name) {
+                Ok(name) if name != note.name => {
+                    map.changed.insert(note.id, Some(note.clone()));
+                    note.name = name;
+                },
+                Ok(_) => {},
+                Err(_) => {
+                    clashes.push(note);
+                    continue;
+                },
+            }
+            match map.name_taken(&note) {
+                true => clashes.push(note),
+                false => map.replace(note),
+            }
+        }
+        for note in &clashes {
+            map.changed.entry(note.id).or_insert_with(|| Some(note.clone()));
+        }
+        (map, clashes)
+    }
+
+    pub fn len(&self) -> usize {
+        self.notes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.notes.is_empty()
+    }
+
+    pub fn get(&self, id: &NoteId) -> Option<&Note> {
+        self.notes.get(id)
+    }
+
+    // Gets a note to change its values.
+    // Its name must be changed with rename, so the index stays in step
+    pub fn get_mut(&mut self, id: &NoteId) -> Option<&mut Note> {
+        self.touch(id);
+        self.notes.get_mut(id)
+    }
+
+    pub fn contains(&self, id: &NoteId) -> bool {
+        self.notes.contains_key(id)
+    }
+
+    // Gets the id of the note with the given name
+    pub fn id_by_name(&self, name: &str) -> Option<NoteId> {
+        self.names.get(&name_key(name)).copied()
+    }
+
+    pub fn get_by_name(&self, name: &str) -> Option<&Note> {
+        self.id_by_name(name).and_then(|id| self.notes.get(&id))
+    }
+
+    pub fn contains_name(&self, name: &str) -> bool {
+        self.names.contains_key(&name_key(name))
+    }
+
+    // Adds a new note, erroring if its name is empty or another note already has it
+    pub fn insert(&mut self, mut note: Note) -> Result<NoteId, TrackerError> {
+        note.name = clean_name(&note.name)?;
+        if self.name_taken(&note) {
+            return Err(TrackerError::NameConflict(note.name));
+        }
+        let id = note.id;
+        self.touch(&id);
+        self.replace(note);
+        Ok(id)
+    }
+
+    // Whether a note other than the given one already has its name
+    fn name_taken(&self, note: &Note) -> bool {
+        self.names.get(&name_key(&note.name)).is_some_and(|id| *id != note.id)
+    }
+
+    // Adds a note, or replaces the note with the same id
+    fn replace(&mut self, note: Note) {
+        if let Some(old) = self.notes.remove(&note.id) {
+            self.names.remove(&name_key(&old.name));
+        }
+        self.names.insert(name_key(&note.name), note.id);
+        self.notes.insert(note.id, note);
+    }
+
+    pub fn remove(&mut self, id: &NoteId) -> Option<Note> {
+        self.touch(id);
+        let note = self.notes.remove(id)?;
+        self.names.remove(&name_key(&note.name));
+        Some(note)
+    }
+
+    pub fn remove_by_name(&mut self, name: &str) -> Option<Note> {
+        let id = self.id_by_name(name)?;
+        self.remove(&id)
+    }
+
+    // Renames a note, erroring if the name is empty or another note already has it.
+    // Returns the name as it was saved
+    pub fn rename(&mut self, id: &NoteId, new_name: &str) -> Result<String, TrackerError> {
+        let new_name = clean_name(new_name)?;
+        let new_key = name_key(&new_name);
+        if self.names.get(&new_key).is_some_and(|other| other != id) {
+            return Err(TrackerError::NameConflict(new_name));
+        }
+        self.touch(id);
+        let note = self.notes.get_mut(id)
+            .ok_or(TrackerError::Custom(format!("No note with id {id}")))?;
+        self.names.remove(&name_key(&note.name));
+        self.names.insert(new_key, *id);
+        note.name = new_name.clone();
+        Ok(new_name)
+    }
+
+    pub fn ids(&self) -> hash_map::Keys<'_, NoteId, Note> {
+        self.notes.keys()
+    }
+
+    pub fn values(&self) -> hash_map::Values<'_, NoteId, Note> {
+        self.notes.values()
+    }
+
+    // Keeps the saved copy of a note the first time it's changed after a save
+    fn touch(&mut self, id: &NoteId) {
+        if let hash_map::Entry::Vacant(entry) = self.changed.entry(*id) {
+            entry.insert(self.notes.get(id).cloned());
+        }
+    }
+
+    // Each note added, changed or removed since the map was last saved,
+    // as it was saved and as it is now
+    pub fn changes(&self) -> impl Iterator<Item = (Option<&Note>, Option<&Note>)> {
+        self.changed.iter()
+            .map(|(id, saved)| (saved.as_ref(), self.notes.get(id)))
+            .filter(|change| match change {
+                (Some(saved), Some(note)) => note.has_changed(saved),
+                (None, None) => false,
+                _ => true,
+            })
+    }
+
+    pub fn has_changes(&self) -> bool {
+        self.changes().next().is_some()
+    }
+
+    // Counts every change as saved
+    pub fn mark_saved(&mut self) {
+        self.changed.clear();
+    }
+
+    // Copy of the notes the filter keeps, e.g. to review only some of them
+    pub fn filtered<F: Fn(&Note) -> bool>(&self, keep: F) -> NoteMap {
+        let mut map = NoteMap::new();
+        for note in self.notes.values().filter(|note| keep(note)) {
+            map.replace(note.clone());
+        }
+        map
+    }
+
+    // Every note in tree order, each with how many notes it sits under.
+    // Notes whose parent isn't in the map are at the top, siblings are sorted by name
+    pub fn tree(&self) -> Vec<(usize, &Note)> {
+        let mut roots: Vec<&Note> = vec![];
+        let mut children: HashMap<NoteId, Vec<&Note>> = HashMap::new();
+        for note in self.notes.values() {
+            match note.parent.filter(|parent| *parent != note.id && self.notes.contains_key(parent)) {
+                Some(parent) => children.entry(parent).or_default().push(note),
+                None => roots.push(note),
+            }
+        }
+        roots.sort_by(|a, b| b.name.cmp(&a.name));
+        let mut stack: Vec<(usize, &Note)> = roots.into_iter().map(|note| (0, note)).collect();
+        let mut tree: Vec<(usize, &Note)> = vec![];
+        let mut seen: HashSet<NoteId> = HashSet::new();
+        while let Some((depth, note)) = stack.pop() {
+            if !seen.insert(note.id) {
+                continue;
+            }
+            tree.push((depth, note));
+            if let Some(below) = children.get_mut(&note.id) {
+                // Pushed in reverse so they come off the stack in name order
+                below.sort_by(|a, b| b.name.cmp(&a.name));
+                stack.extend(below.iter().map(|child| (depth + 1, *child)));
+            }
+        }
+        // Notes whose parents loop back round never reach the top, so they're listed there
+        if tree.len() < self.notes.len() {
+            tree.extend(self.notes.values()
+                .filter(|note| !seen.contains(&note.id))
+                .sorted_by(|a, b| a.name.cmp(&b.name))
+                .map(|note| (0, note)));
+        }
+        tree
+    }
+
+    // Copy of the given note and every note below it, e.g. to review a whole file
+    pub fn subtree(&self, id: &NoteId) -> NoteMap {
+        let mut ids: HashSet<NoteId> = HashSet::from([*id]);
+        loop {
+            let found = ids.len();
+            for note in self.notes.values() {
+                if note.parent.is_some_and(|parent| ids.contains(&parent)) {
+                    ids.insert(note.id);
+                }
+            }
+            if ids.len() == found {
+                break;
+            }
+        }
+        self.filtered(|note| ids.contains(&note.id))
+    }
+
+    pub fn iter(&self) -> hash_map::Iter<'_, NoteId, Note> {
+        self.notes.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a NoteMap {
+    type Item = (&'a NoteId, &'a Note);
+    type IntoIter = hash_map::Iter<'a, NoteId, Note>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Local;
+
+    fn note(name: &str) -> Note {
+        Note::new(name.to_string(), 0, Local::now())
+    }
+
+    #[test]
+    fn ids_stay_with_their_notes() {
+        let mut note_map = NoteMap::new();
+        let graphs = note_map.insert(note("Graphs")).unwrap();
+        note_map.get_mut(&graphs).unwrap().freq = 3;
+        note_map.rename(&graphs, "Networks").unwrap();
+        let renamed = note_map.get(&graphs).unwrap();
+        assert_eq!((renamed.name.as_str(), renamed.freq), ("Networks", 3));
+        assert_eq!(note_map.remove_by_name("networks").map(|note| note.id), Some(graphs));
+        assert!(note_map.is_empty() && !note_map.contains(&graphs));
+    }
+
+    #[test]
+    fn loaded_clashes_are_returned() {
+        let (note_map, clashes) = NoteMap::from_notes(vec![note("Graphs"), note("GRAPHS"), note(" ")]);
+        assert_eq!(note_map.len(), 1);
+        assert_eq!(clashes.len(), 2);
+    }
+
+    #[test]
+    fn names_collide_ignoring_case_and_compatibility_forms() {
+        assert_eq!(name_key("Graphs"), name_key(" graphs "));
+        assert_eq!(name_key("Graphs"), name_key("ｇｒａｐｈｓ"));
+        assert_eq!(name_key("ﬁle"), name_key("FILE"));
+        // Composed and decomposed accents
+        assert_eq!(name_key("Caf\u{e9}"), name_key("cafe\u{301}"));
+        assert_ne!(name_key("Graphs"), name_key("Graph"));
+    }
+
+    #[test]
+    fn clashing_names_are_refused() {
+        let mut note_map = NoteMap::new();
+        let id = note_map.insert(note(" Graphs ")).unwrap();
+        assert_eq!(note_map.get(&id).unwrap().name, "Graphs");
+        assert!(matches!(note_map.insert(note("ｇｒａｐｈｓ")), Err(TrackerError::NameConflict(_))));
+        assert!(matches!(note_map.insert(note("  ")), Err(TrackerError::InvalidName(_))));
+        assert_eq!(note_map.id_by_name("GRAPHS"), Some(id));
+    }
+
+    #[test]
+    fn renaming_keeps_the_index_in_step() {
+        let mut note_map = NoteMap::new();
+        let graphs = note_map.insert(note("Graphs")).unwrap();
+        let trees = note_map.insert(note("Trees")).unwrap();
+        assert!(note_map.rename(&trees, "graphs").is_err());
+        // Changing only the case of its own name is fine
+        assert_eq!(note_map.rename(&graphs, "GRAPHS").unwrap(), "GRAPHS");
+        assert_eq!(note_map.rename(&graphs, "Networks").unwrap(), "Networks");
+        assert!(!note_map.contains_name("graphs"));
+        assert_eq!(note_map.id_by_name("networks"), Some(graphs));
+    }
+
+    #[test]
+    fn header_names_are_scoped_to_the_note_above() {
+        assert_eq!(scoped_name("algo-", Some("algo-graphs"), "BFS"), "algo-graphs › BFS");
+        assert_eq!(scoped_name("algo-", None, "BFS"), "algo-BFS");
+    }
+
+    #[test]
+    fn changes_are_kept_until_saved() {
+        let (mut note_map, _) = NoteMap::from_notes(vec![note("Graphs"), note(" Trees"), note("Sets")]);
+        // Only the tidied name differs from what was loaded
+        assert_eq!(note_map.changes().map(|(saved, _)| saved.unwrap().name.as_str()).collect_vec(), [" Trees"]);
+        note_map.mark_saved();
+        assert!(!note_map.has_changes());
+
+        let graphs = note_map.id_by_name("Graphs").unwrap();
+        let sets = note_map.id_by_name("Sets").unwrap();
+        // Looking at a note without changing it isn't a change
+        note_map.get_mut(&graphs).unwrap();
+        assert!(!note_map.has_changes());
+        note_map.get_mut(&graphs).unwrap().freq = 2;
+        note_map.rename(&graphs, "Networks").unwrap();
+        note_map.remove(&sets);
+        let arrays = note_map.insert(note("Arrays")).unwrap();
+        let changes: HashMap<NoteId, (Option<Note>, Option<Note>)> = note_map.changes()
+            .map(|(saved, now)| (saved.or(now).unwrap().id, (saved.cloned(), now.cloned())))
+            .collect();
+        assert_eq!(changes.len(), 3);
+        let (saved, now) = &changes[&graphs];
+        assert_eq!(saved.as_ref().map(|n| (n.name.as_str(), n.freq)), Some(("Graphs", 0)));
+        assert_eq!(now.as_ref().map(|n| (n.name.as_str(), n.freq)), Some(("Networks", 2)));
+        assert!(changes[&sets].1.is_none() && changes[&arrays].0.is_none());
+
+        // Put back how it was saved
+        note_map.remove(&arrays);
+        note_map.get_mut(&graphs).unwrap().freq = 0;
+        note_map.rename(&graphs, "Graphs").unwrap();
+        note_map.insert(changes[&sets].0.clone().unwrap()).unwrap();
+        assert!(!note_map.has_changes());
+    }
+}
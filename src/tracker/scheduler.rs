@@ -0,0 +1,652 @@
+use crate::storage::{FsrsState, Note, ReviewOutcome, Settings};
+use std::fmt;
+use itertools::Itertools;
+use chrono::{prelude::*, TimeDelta};
+use serde::{Deserialize, Serialize};
+
+use super::NoteMap;
+
+// A titled group of notes picked for review, e.g. "Least Reviewed"
+pub struct ReviewSection {
+    pub title: String,
+    pub notes: Vec<Note>,
+}
+
+// Policy deciding which notes are suggested when a review is generated
+pub trait Scheduler {
+    // Name shown in menus and review titles
+    fn name(&self) -> &'static str;
+
+    // Picks the notes to review as of now, grouped into sections for display
+    fn get_notes_to_review(&self, note_map: &NoteMap, now: DateTime<Local>) -> Vec<ReviewSection>;
+
+    // Grades the user picks from for each reviewed note
+    fn grades(&self) -> &'static [&'static str] {
+        &["Recalled", "Hard", "Forgot"]
+    }
+
+    // Converts the index of a picked grade into the outcome saved on the note
+    fn outcome(&self, grade: usize) -> ReviewOutcome {
+        match grade {
+            0 => ReviewOutcome::Recalled,
+            1 => ReviewOutcome::Hard,
+            _ => ReviewOutcome::Forgot,
+        }
+    }
+
+    // Updates a note reviewed at now, grade is the index of the picked grade
+    fn review_note(&self, note: &mut Note, _grade: usize, now: DateTime<Local>) {
+        note.freq += 1;
+        note.last_accessed = now;
+    }
+}
+
+// Schedulers that can be chosen for a collection.
+// Saved in the collections settings so each one keeps its own policy
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum SchedulerKind {
+    #[default]
+    Frequency,
+    Sm2,
+    Fsrs,
+    Leitner,
+}
+
+impl SchedulerKind {
+    pub const ALL: &'static [SchedulerKind] = &[
+        SchedulerKind::Frequency,
+        SchedulerKind::Sm2,
+        SchedulerKind::Fsrs,
+        SchedulerKind::Leitner,
+    ];
+
+    // Creates the scheduler this kind refers to, configured from the collections settings
+    pub fn scheduler(&self, settings: &Settings) -> Box<dyn Scheduler> {
+        match self {
+            SchedulerKind::Frequency => Box::new(FrequencyScheduler::default()),
+            SchedulerKind::Sm2 => Box::new(Sm2Scheduler::default()),
+            SchedulerKind::Fsrs => Box::new(FsrsScheduler::new(settings.fsrs_retention)),
+            SchedulerKind::Leitner => Box::new(LeitnerScheduler::new(settings.leitner_cadence.clone())),
+        }
+    }
+}
+
+impl fmt::Display for SchedulerKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.scheduler(&Settings::default()).name())
+    }
+}
+
+// Default scheduler, suggests the least reviewed notes
+// followed by the ones that have gone the longest without a review
+pub struct FrequencyScheduler {
+    pub uncommon: usize,
+    pub oldest: usize,
+}
+
+impl Default for FrequencyScheduler {
+    fn default() -> Self {
+        Self { uncommon: 3, oldest: 2 }
+    }
+}
+
+impl Scheduler for FrequencyScheduler {
+    fn name(&self) -> &'static str {
+        "Least reviewed + oldest"
+    }
+
+    // Sorts notes by which ones have been reviewed the least and which ones are the oldest
+    fn get_notes_to_review(&self, note_map: &NoteMap, _now: DateTime<Local>) -> Vec<ReviewSection> {
+        let mut notes: Vec<&Note> = note_map.values().collect();
+
+        // Sort by freq
+        notes.sort_by_key(|n| n.freq);
+        // Gets most uncommon
+        let uncommon = notes.iter().take(self.uncommon).cloned().cloned().collect_vec();
+
+        // Sort by date
+        notes.sort_by_key(|n| n.last_accessed);
+        // Gets oldest that arn't already in the most uncommon vec
+        let oldest = notes.iter()
+            .filter(|n| !uncommon.contains(n))
+            .take(self.oldest).cloned().cloned().collect_vec();
+
+        vec![
+            ReviewSection { title: "Least Reviewed:".to_string(), notes: uncommon },
+            ReviewSection { title: "Oldest Since Last Review:".to_string(), notes: oldest },
+        ]
+    }
+}
+
+// SM-2 spaced repetition, each note keeps its own ease and interval
+// which grow when it's recalled well and reset when it's forgotten.
+// https://super-memory.com/english/ol/sm2.htm
+pub struct Sm2Scheduler {
+    // Most never reviewed notes introduced per review
+    pub new_notes: usize,
+    // Most due notes suggested per review
+    pub max_due: usize,
+}
+
+impl Default for Sm2Scheduler {
+    fn default() -> Self {
+        Self { new_notes: 5, max_due: 20 }
+    }
+}
+
+impl Sm2Scheduler {
+    // Lowest ease a note can drop to, stops intervals from collapsing
+    const MIN_EASE: f32 = 1.3;
+    // Grades at or above this count as recalled
+    const PASS_GRADE: usize = 3;
+}
+
+impl Scheduler for Sm2Scheduler {
+    fn name(&self) -> &'static str {
+        "SM-2 spaced repetition"
+    }
+
+    // Gets notes whose due date has passed, most overdue first,
+    // along with a few notes that haven't been scheduled yet
+    fn get_notes_to_review(&self, note_map: &NoteMap, now: DateTime<Local>) -> Vec<ReviewSection> {
+        let mut due: Vec<(DateTime<Local>, &Note)> = note_map.values()
+            .filter_map(|n| {
+                let due = n.sm2.due?;
+                (due <= now).then_some((due, n))
+            })
+            .collect();
+        due.sort_by_key(|d| d.0);
+
+        let new = note_map.values()
+            .filter(|n| n.sm2.due.is_none())
+            .sorted_by(|a, b| a.name.cmp(&b.name))
+            .take(self.new_notes).cloned().collect_vec();
+
+        vec![
+            ReviewSection {
+                title: "Due:".to_string(),
+                notes: due.into_iter().take(self.max_due).map(|(_, n)| n.clone()).collect(),
+            },
+            ReviewSection { title: "New:".to_string(), notes: new },
+        ]
+    }
+
+    fn grades(&self) -> &'static [&'static str] {
+        &[
+            "0 - Complete blackout",
+            "1 - Wrong, but recognised the answer",
+            "2 - Wrong, but the answer seemed easy",
+            "3 - Right, with serious difficulty",
+            "4 - Right, after some hesitation",
+            "5 - Perfect recall",
+        ]
+    }
+
+    fn outcome(&self, grade: usize) -> ReviewOutcome {
+        match grade {
+            4.. => ReviewOutcome::Recalled,
+            Self::PASS_GRADE => ReviewOutcome::Hard,
+            _ => ReviewOutcome::Forgot,
+        }
+    }
+
+    // Applies the SM-2 update for the given 0-5 grade and schedules the next review
+    fn review_note(&self, note: &mut Note, grade: usize, now: DateTime<Local>) {
+        let sm2 = &mut note.sm2;
+        if grade >= Self::PASS_GRADE {
+            sm2.interval = match sm2.repetitions {
+                0 => 1,
+                1 => 6,
+                _ => (sm2.interval as f32 * sm2.ease).round() as u32,
+            };
+            sm2.repetitions += 1;
+        } else {
+            sm2.repetitions = 0;
+            sm2.interval = 1;
+        }
+        let miss = (5 - grade.min(5)) as f32;
+        sm2.ease = (sm2.ease + 0.1 - miss * (0.08 + miss * 0.02)).max(Self::MIN_EASE);
+        sm2.due = Some(now + TimeDelta::days(sm2.interval as i64));
+
+        note.freq += 1;
+        note.last_accessed = now;
+    }
+}
+
+// FSRS (v4.5) scheduler, models each notes memory with a stability and difficulty
+// and suggests the notes whose chance of recall has fallen the furthest.
+// https://github.com/open-spaced-repetition/fsrs4anki/wiki/The-Algorithm
+pub struct FsrsScheduler {
+    // Chance of recall to keep notes above, between 0 and 1
+    pub retention: f32,
+    // Most due notes suggested per review
+    pub max_due: usize,
+    // Most never reviewed notes introduced per review
+    pub new_notes: usize,
+    pub weights: [f32; 17],
+}
+
+impl FsrsScheduler {
+    const DECAY: f32 = -0.5;
+    // Makes retrievability 90% after stability days
+    const FACTOR: f32 = 19.0 / 81.0;
+    const DEFAULT_WEIGHTS: [f32; 17] = [
+        0.4872, 1.4003, 3.7145, 13.8206, 5.1618, 1.2298, 0.8975, 0.031, 1.6474,
+        0.1367, 1.0461, 2.1072, 0.0793, 0.3246, 1.587, 0.2272, 2.8755,
+    ];
+
+    pub fn new(retention: f32) -> Self {
+        Self {
+            retention: retention.clamp(0.5, 0.99),
+            max_due: 20,
+            new_notes: 5,
+            weights: Self::DEFAULT_WEIGHTS,
+        }
+    }
+
+    // Chance the note can be recalled now, None if it has never been reviewed
+    pub fn retrievability(&self, note: &Note, now: DateTime<Local>) -> Option<f32> {
+        Self::state_retrievability(&self.state(note), now)
+    }
+
+    fn state_retrievability(state: &FsrsState, now: DateTime<Local>) -> Option<f32> {
+        let last_review = state.last_review?;
+        let elapsed_days = (now - last_review).num_seconds().max(0) as f32 / 86_400.0;
+        Some(Self::forgetting_curve(elapsed_days, state.stability))
+    }
+
+    // The notes FSRS state, notes reviewed before switching to FSRS
+    // get theirs by replaying their review history
+    pub fn state(&self, note: &Note) -> FsrsState {
+        if note.fsrs.last_review.is_some() {
+            return note.fsrs.clone();
+        }
+        note.history.iter()
+            .filter_map(|entry| Some((entry.outcome?, entry.time)))
+            .fold(note.fsrs.clone(), |state, (outcome, time)| {
+                let grade = match outcome {
+                    ReviewOutcome::Forgot => 0,
+                    ReviewOutcome::Hard => 1,
+                    ReviewOutcome::Recalled => 2,
+                };
+                self.next_state(&state, grade, time)
+            })
+    }
+
+    // State after reviewing with the given grade at the given time,
+    // grade 0-3 being Again-Easy
+    fn next_state(&self, state: &FsrsState, grade: usize, now: DateTime<Local>) -> FsrsState {
+        let w = &self.weights;
+        let rating = (grade.min(3) + 1) as f32;
+
+        let (stability, difficulty) = match Self::state_retrievability(state, now) {
+            None => (w[rating as usize - 1], self.initial_difficulty(rating)),
+            Some(r) => {
+                let (s, d) = (state.stability.max(0.01), state.difficulty);
+                let stability = if rating == 1.0 {
+                    w[11] * d.powf(-w[12]) * ((s + 1.0).powf(w[13]) - 1.0) * (w[14] * (1.0 - r)).exp()
+                } else {
+                    let hard_penalty = if rating == 2.0 { w[15] } else { 1.0 };
+                    let easy_bonus = if rating == 4.0 { w[16] } else { 1.0 };
+                    s * (1.0 + w[8].exp() * (11.0 - d) * s.powf(-w[9])
+                        * ((w[10] * (1.0 - r)).exp() - 1.0) * hard_penalty * easy_bonus)
+                };
+                // Moves difficulty towards the default so it can't get stuck at an extreme
+                let next_d = d - w[6] * (rating - 3.0);
+                let difficulty = (w[7] * self.initial_difficulty(3.0) + (1.0 - w[7]) * next_d)
+                    .clamp(1.0, 10.0);
+                (stability, difficulty)
+            },
+        };
+        FsrsState { stability, difficulty, last_review: Some(now) }
+    }
+
+    fn forgetting_curve(elapsed_days: f32, stability: f32) -> f32 {
+        (1.0 + Self::FACTOR * elapsed_days / stability.max(0.01)).powf(Self::DECAY)
+    }
+
+    // Difficulty of a note after its first review with the given 1-4 rating
+    fn initial_difficulty(&self, rating: f32) -> f32 {
+        let w = &self.weights;
+        (w[4] - (rating - 3.0) * w[5]).clamp(1.0, 10.0)
+    }
+}
+
+impl Scheduler for FsrsScheduler {
+    fn name(&self) -> &'static str {
+        "FSRS memory model"
+    }
+
+    // Gets notes whose retrievability has fallen below the target retention,
+    // least likely to be recalled first, along with a few unscheduled notes
+    fn get_notes_to_review(&self, note_map: &NoteMap, now: DateTime<Local>) -> Vec<ReviewSection> {
+        let forgetting = note_map.values()
+            .filter_map(|n| self.retrievability(n, now).map(|r| (r, n)))
+            .filter(|(r, _)| *r < self.retention)
+            .sorted_by(|a, b| a.0.total_cmp(&b.0))
+            .take(self.max_due)
+            .map(|(_, n)| n.clone())
+            .collect_vec();
+
+        let new = note_map.values()
+            .filter(|n| self.retrievability(n, now).is_none())
+            .sorted_by(|a, b| a.name.cmp(&b.name))
+            .take(self.new_notes).cloned().collect_vec();
+
+        vec![
+            ReviewSection {
+                title: format!("Likely Forgotten (below {:.0}% recall):", self.retention * 100.0),
+                notes: forgetting,
+            },
+            ReviewSection { title: "New:".to_string(), notes: new },
+        ]
+    }
+
+    fn grades(&self) -> &'static [&'static str] {
+        &["Again", "Hard", "Good", "Easy"]
+    }
+
+    fn outcome(&self, grade: usize) -> ReviewOutcome {
+        match grade {
+            0 => ReviewOutcome::Forgot,
+            1 => ReviewOutcome::Hard,
+            _ => ReviewOutcome::Recalled,
+        }
+    }
+
+    // Updates the notes stability and difficulty for the given grade,
+    // grade 0-3 being Again-Easy
+    fn review_note(&self, note: &mut Note, grade: usize, now: DateTime<Local>) {
+        note.fsrs = self.next_state(&self.state(note), grade, now);
+
+        note.freq += 1;
+        note.last_accessed = now;
+    }
+}
+
+// Leitner boxes, recalled notes move up a box and forgotten notes go back to box 1.
+// Each box is reviewed on its own cadence, so notes in higher boxes come up less often
+pub struct LeitnerScheduler {
+    // Days between reviews for each box, box 1 first
+    pub cadence: Vec<u32>,
+    // Most never reviewed notes introduced per review
+    pub new_notes: usize,
+}
+
+impl LeitnerScheduler {
+    pub fn new(cadence: Vec<u32>) -> Self {
+        // Always keep at least one box
+        let cadence = if cadence.is_empty() { vec![1] } else { cadence };
+        Self { cadence, new_notes: 5 }
+    }
+
+    // Days between reviews for the given box, boxes past the last use the last cadence
+    fn box_cadence(&self, box_number: usize) -> u32 {
+        let index = box_number.clamp(1, self.cadence.len()) - 1;
+        self.cadence[index]
+    }
+
+    // Whether the note has gone at least its boxes cadence without a review
+    fn is_due(&self, note: &Note, now: DateTime<Local>) -> bool {
+        match note.leitner.last_review {
+            Some(last_review) => now - last_review >= TimeDelta::days(self.box_cadence(note.leitner.box_number) as i64),
+            None => false,
+        }
+    }
+}
+
+impl Scheduler for LeitnerScheduler {
+    fn name(&self) -> &'static str {
+        "Leitner boxes"
+    }
+
+    // Gets the due notes from each box, lowest box first,
+    // along with a few notes that haven't been placed in a box yet
+    fn get_notes_to_review(&self, note_map: &NoteMap, now: DateTime<Local>) -> Vec<ReviewSection> {
+        let mut sections = (1..=self.cadence.len()).map(|box_number| {
+            ReviewSection {
+                title: format!("Box {} (every {} days):", box_number, self.box_cadence(box_number)),
+                notes: note_map.values()
+                    .filter(|n| n.leitner.box_number.clamp(1, self.cadence.len()) == box_number)
+                    .filter(|n| self.is_due(n, now))
+                    .sorted_by(|a, b| a.name.cmp(&b.name))
+                    .cloned().collect_vec(),
+            }
+        })
+        .filter(|s| !s.notes.is_empty())
+        .collect_vec();
+
+        sections.push(ReviewSection {
+            title: "New:".to_string(),
+            notes: note_map.values()
+                .filter(|n| n.leitner.last_review.is_none())
+                .sorted_by(|a, b| a.name.cmp(&b.name))
+                .take(self.new_notes).cloned().collect_vec(),
+        });
+        sections
+    }
+
+    fn grades(&self) -> &'static [&'static str] {
+        &["Remembered", "Forgot"]
+    }
+
+    fn outcome(&self, grade: usize) -> ReviewOutcome {
+        match grade {
+            0 => ReviewOutcome::Recalled,
+            _ => ReviewOutcome::Forgot,
+        }
+    }
+
+    // Promotes the note a box when remembered, otherwise sends it back to box 1
+    fn review_note(&self, note: &mut Note, grade: usize, now: DateTime<Local>) {
+        note.leitner.box_number = match grade {
+            0 => (note.leitner.box_number + 1).min(self.cadence.len()),
+            _ => 1,
+        };
+        note.leitner.last_review = Some(now);
+
+        note.freq += 1;
+        note.last_accessed = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{LeitnerState, ReviewEntry};
+
+    fn day(day: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(2024, 3, day, 9, 0, 0).unwrap()
+    }
+
+    fn note(name: &str) -> Note {
+        Note::new(name.to_string(), 0, day(1))
+    }
+
+    fn names(section: &ReviewSection) -> Vec<&str> {
+        section.notes.iter().map(|n| n.name.as_str()).collect()
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 1e-4, "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn sm2_intervals_grow_until_a_failed_grade() {
+        let scheduler = Sm2Scheduler::default();
+        let mut note = note("Graphs");
+        let mut review = |grade, at| {
+            scheduler.review_note(&mut note, grade, at);
+            (note.sm2.interval, note.sm2.repetitions, note.sm2.ease, note.sm2.due)
+        };
+
+        let (interval, repetitions, ease, due) = review(5, day(1));
+        assert_eq!((interval, repetitions, due), (1, 1, Some(day(2))));
+        assert_close(ease, 2.6);
+        let (interval, repetitions, ease, due) = review(5, day(2));
+        assert_eq!((interval, repetitions, due), (6, 2, Some(day(8))));
+        assert_close(ease, 2.7);
+        // 6 * 2.7 rounded
+        let (interval, repetitions, ease, due) = review(4, day(8));
+        assert_eq!((interval, repetitions, due), (16, 3, Some(day(24))));
+        assert_close(ease, 2.7);
+        // Below 3 starts the repetitions over but still costs ease
+        let (interval, repetitions, ease, due) = review(2, day(24));
+        assert_eq!((interval, repetitions, due), (1, 0, Some(day(25))));
+        assert_close(ease, 2.38);
+        assert_eq!(note.freq, 4);
+        assert_eq!(note.last_accessed, day(24));
+    }
+
+    #[test]
+    fn sm2_ease_stops_at_the_floor() {
+        let scheduler = Sm2Scheduler::default();
+        let mut note = note("Graphs");
+        scheduler.review_note(&mut note, 0, day(1));
+        assert_close(note.sm2.ease, 1.7);
+        scheduler.review_note(&mut note, 0, day(2));
+        assert_close(note.sm2.ease, 1.3);
+        scheduler.review_note(&mut note, 1, day(3));
+        assert_close(note.sm2.ease, 1.3);
+    }
+
+    #[test]
+    fn sm2_suggests_overdue_notes_first_then_new_ones() {
+        let scheduler = Sm2Scheduler { new_notes: 2, max_due: 20 };
+        let mut notes = vec![];
+        for (name, due) in [("Late", Some(day(3))), ("Later", Some(day(1))), ("Upcoming", Some(day(12))),
+                ("Trees", None), ("Arrays", None), ("Sets", None)] {
+            let mut note = note(name);
+            note.sm2.due = due;
+            notes.push(note);
+        }
+        let (note_map, _) = NoteMap::from_notes(notes);
+
+        let sections = scheduler.get_notes_to_review(&note_map, day(10));
+        assert_eq!(names(&sections[0]), ["Later", "Late"]);
+        assert_eq!(names(&sections[1]), ["Arrays", "Sets"]);
+    }
+
+    #[test]
+    fn fsrs_first_review_uses_the_initial_weights() {
+        let scheduler = FsrsScheduler::new(0.9);
+        let mut good = note("Graphs");
+        scheduler.review_note(&mut good, 2, day(1));
+        assert_close(good.fsrs.stability, 3.7145);
+        assert_close(good.fsrs.difficulty, 5.1618);
+        let mut again = note("Trees");
+        scheduler.review_note(&mut again, 0, day(1));
+        assert_close(again.fsrs.stability, 0.4872);
+        assert_close(again.fsrs.difficulty, 7.6214);
+
+        // Stability is the days until recall falls to 90%
+        let after = day(1) + TimeDelta::seconds((3.7145 * 86_400.0) as i64);
+        assert_close(scheduler.retrievability(&good, after).unwrap(), 0.9);
+        assert_eq!(scheduler.retrievability(&note("Sets"), day(1)), None);
+    }
+
+    #[test]
+    fn fsrs_forgetting_lowers_stability() {
+        let scheduler = FsrsScheduler::new(0.9);
+        let mut recalled = note("Graphs");
+        scheduler.review_note(&mut recalled, 2, day(1));
+        let mut forgot = recalled.clone();
+        scheduler.review_note(&mut recalled, 2, day(5));
+        scheduler.review_note(&mut forgot, 0, day(5));
+        assert!(recalled.fsrs.stability > 3.7145);
+        assert!(forgot.fsrs.stability < 3.7145);
+        assert!(forgot.fsrs.difficulty > recalled.fsrs.difficulty);
+        assert_eq!(forgot.fsrs.last_review, Some(day(5)));
+    }
+
+    #[test]
+    fn fsrs_replays_history_from_other_schedulers() {
+        let scheduler = FsrsScheduler::new(0.9);
+        let mut reviewed = note("Graphs");
+        scheduler.review_note(&mut reviewed, 2, day(1));
+        scheduler.review_note(&mut reviewed, 0, day(4));
+
+        let mut switched = note("Graphs");
+        for (time, outcome) in [(day(1), ReviewOutcome::Recalled), (day(4), ReviewOutcome::Forgot)] {
+            switched.history.push(ReviewEntry { time, outcome: Some(outcome), time_spent: None, scheduler: Some(SchedulerKind::Sm2) });
+        }
+        // Manual updates aren't reviews
+        switched.history.push(ReviewEntry { time: day(6), outcome: None, time_spent: None, scheduler: None });
+        assert_eq!(scheduler.state(&switched), reviewed.fsrs);
+
+        let (note_map, _) = NoteMap::from_notes(vec![switched, note("Trees")]);
+        let sections = scheduler.get_notes_to_review(&note_map, day(20));
+        assert_eq!(names(&sections[0]), ["Graphs"]);
+        assert_eq!(names(&sections[1]), ["Trees"]);
+    }
+
+    #[test]
+    fn fsrs_suggests_notes_below_the_target_retention() {
+        let scheduler = FsrsScheduler::new(0.9);
+        let mut notes = vec![];
+        for (name, grade) in [("Graphs", 0), ("Trees", 2), ("Sets", 3)] {
+            let mut note = note(name);
+            scheduler.review_note(&mut note, grade, day(1));
+            notes.push(note);
+        }
+        let (note_map, _) = NoteMap::from_notes(notes);
+
+        assert!(names(&scheduler.get_notes_to_review(&note_map, day(1))[0]).is_empty());
+        // Least likely to be recalled first, Sets is still above 90%
+        assert_eq!(names(&scheduler.get_notes_to_review(&note_map, day(6))[0]), ["Graphs", "Trees"]);
+    }
+
+    #[test]
+    fn leitner_moves_notes_between_boxes() {
+        let scheduler = LeitnerScheduler::new(vec![1, 3, 7]);
+        let mut note = note("Graphs");
+        let mut boxes = vec![];
+        for grade in [0, 0, 0, 1, 0] {
+            scheduler.review_note(&mut note, grade, day(1));
+            boxes.push(note.leitner.box_number);
+        }
+        // Stops at the last box, forgetting goes back to the first
+        assert_eq!(boxes, [2, 3, 3, 1, 2]);
+        assert_eq!(note.leitner.last_review, Some(day(1)));
+    }
+
+    #[test]
+    fn leitner_suggests_boxes_on_their_cadence() {
+        let scheduler = LeitnerScheduler::new(vec![1, 3]);
+        let mut notes = vec![note("New")];
+        for (name, box_number) in [("Graphs", 1), ("Trees", 2)] {
+            let mut note = note(name);
+            note.leitner = LeitnerState { box_number, last_review: Some(day(1)) };
+            notes.push(note);
+        }
+        let (note_map, _) = NoteMap::from_notes(notes);
+
+        let sections = scheduler.get_notes_to_review(&note_map, day(2));
+        assert_eq!(sections.iter().map(|s| s.title.as_str()).collect_vec(), ["Box 1 (every 1 days):", "New:"]);
+        assert_eq!(names(&sections[0]), ["Graphs"]);
+        assert_eq!(names(&sections[1]), ["New"]);
+        let sections = scheduler.get_notes_to_review(&note_map, day(4));
+        assert_eq!(names(&sections[1]), ["Trees"]);
+    }
+
+    #[test]
+    fn leitner_clamps_boxes_when_the_cadence_shrinks() {
+        let mut note = note("Graphs");
+        note.leitner = LeitnerState { box_number: 5, last_review: Some(day(1)) };
+        let (note_map, _) = NoteMap::from_notes(vec![note.clone()]);
+        let scheduler = LeitnerScheduler::new(vec![1, 3]);
+
+        // Box 5 is treated as the last box
+        assert!(scheduler.get_notes_to_review(&note_map, day(3)).iter().all(|s| s.notes.is_empty()));
+        let sections = scheduler.get_notes_to_review(&note_map, day(4));
+        assert_eq!(sections[0].title, "Box 2 (every 3 days):");
+        assert_eq!(names(&sections[0]), ["Graphs"]);
+
+        scheduler.review_note(&mut note, 0, day(4));
+        assert_eq!(note.leitner.box_number, 2);
+        // An empty cadence still leaves one box
+        let scheduler = LeitnerScheduler::new(vec![]);
+        scheduler.review_note(&mut note, 0, day(5));
+        assert_eq!(note.leitner.box_number, 1);
+    }
+}
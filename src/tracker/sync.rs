@@ -0,0 +1,400 @@
+// Keeps the notes imported from a directory of markdown files in step with it.
+// Each header is matched to the note imported from it, first by its text within the same file,
+// then by its place in the file, which catches headers that were renamed. Files that were moved
+// or renamed are matched to a missing file with the same name, or the one sharing the most headers.
+// Matched notes keep their reviews and history, new headers are added, and notes whose
+// header has gone are returned so they can be archived
+use std::{collections::{BTreeSet, HashMap, HashSet}, iter, path::{Path, PathBuf}};
+use chrono::Local;
+
+use crate::storage::{Heading, Note, NoteId, NoteSource};
+use super::{name_key, scoped_name, NoteMap, TrackerError, SCOPE_SEPARATOR};
+
+// A markdown file in the directory being synced
+pub struct SyncFile {
+    pub path: PathBuf,
+    // File name without .md, the name of the note made for the file
+    pub name: String,
+    pub headings: Vec<Heading>,
+    // Tags for notes added from the file
+    pub tags: BTreeSet<String>,
+}
+
+impl SyncFile {
+    // Source of the file and each of its headers, in order
+    fn sources(&self) -> Vec<NoteSource> {
+        let mut sources = vec![NoteSource { path: self.path.clone(), heading: None, position: 0 }];
+        sources.extend(self.headings.iter().enumerate().map(|(index, heading)| NoteSource {
+            path: self.path.clone(),
+            heading: Some(heading.name.clone()),
+            position: index + 1,
+        }));
+        sources
+    }
+}
+
+// What syncing will change, worked out before anything is changed
+#[derive(Debug, Default)]
+pub struct SyncPlan {
+    // Notes matched to a header, with where that header is now
+    pub matched: Vec<(NoteId, NoteSource)>,
+    // Headers no note was imported from
+    pub added: Vec<NoteSource>,
+    // Notes imported from the directory whose header has gone, not counting archived ones
+    pub removed: Vec<NoteId>,
+    // Matched notes that keep their name when the note above them is renamed or they move
+    pub keep_names: HashSet<NoteId>,
+}
+
+// What syncing changed
+#[derive(Debug, Default)]
+pub struct SyncReport {
+    pub added: usize,
+    // Old and new name of each renamed note
+    pub renamed: Vec<(String, String)>,
+    // Files that were moved or renamed
+    pub moved: usize,
+    // Archived notes whose header came back
+    pub restored: usize,
+    // Why headers couldn't be added or renamed
+    pub skipped: Vec<String>,
+}
+
+// Text a source is named after, its header or the files name
+pub fn source_text(source: &NoteSource) -> String {
+    match &source.heading {
+        Some(heading) => heading.clone(),
+        None => source.path.file_stem().map_or(String::new(), |stem| stem.to_string_lossy().to_string()),
+    }
+}
+
+// Matches the files in the directory to the notes imported from below the root
+pub fn plan_sync(note_map: &NoteMap, root: &Path, files: &[SyncFile]) -> SyncPlan {
+    // Notes imported from below the root that haven't been matched yet
+    let mut unmatched: HashMap<NoteId, NoteSource> = note_map.values()
+        .filter_map(|note| note.source.clone()
+            .filter(|source| source.path.starts_with(root))
+            .map(|source| (note.id, source)))
+        .collect();
+    let noted_paths: HashSet<PathBuf> = unmatched.values().map(|source| source.path.clone()).collect();
+    let file_paths: HashSet<&PathBuf> = files.iter().map(|file| &file.path).collect();
+    // Files notes were imported from that aren't there anymore, which moved files are matched to
+    let mut missing: Vec<PathBuf> = noted_paths.iter()
+        .filter(|path| !file_paths.contains(path))
+        .cloned()
+        .collect();
+    missing.sort();
+
+    let mut plan = SyncPlan::default();
+    for file in files {
+        // Path the files notes were imported from, which is different if it was moved
+        let old_path = match noted_paths.contains(&file.path) {
+            true => file.path.clone(),
+            false => match find_moved(file, &missing, &unmatched) {
+                Some(path) => {
+                    missing.retain(|m| *m != path);
+                    path
+                },
+                None => file.path.clone(),
+            },
+        };
+
+        // Headers with the same text, closest to where the note was first
+        let mut left: Vec<NoteSource> = vec![];
+        for source in file.sources() {
+            let found = unmatched.iter()
+                .filter(|(_, old)| old.path == old_path && old.heading == source.heading)
+                .min_by_key(|(id, old)| (old.position.abs_diff(source.position), **id))
+                .map(|(id, _)| *id);
+            match found {
+                Some(id) => {
+                    unmatched.remove(&id);
+                    plan.matched.push((id, source));
+                },
+                None => left.push(source),
+            }
+        }
+        // Headers in the same place with different text, which were renamed
+        for source in left {
+            let found = unmatched.iter()
+                .filter(|(_, old)| old.path == old_path && old.position == source.position)
+                .map(|(id, _)| *id)
+                .min();
+            match found {
+                Some(id) => {
+                    unmatched.remove(&id);
+                    plan.matched.push((id, source));
+                },
+                None => plan.added.push(source),
+            }
+        }
+    }
+
+    plan.removed = unmatched.into_keys()
+        .filter(|id| note_map.get(id).is_some_and(|note| !note.archived))
+        .collect();
+    plan
+}
+
+// Finds the missing file a new file was most likely moved or renamed from. One with the same
+// file name, otherwise the one whose notes share the most of its headers, at least half of them
+fn find_moved(file: &SyncFile, missing: &[PathBuf], unmatched: &HashMap<NoteId, NoteSource>) -> Option<PathBuf> {
+    if let Some(path) = missing.iter().find(|path| path.file_name() == file.path.file_name()) {
+        return Some(path.clone());
+    }
+    let headings: HashSet<&str> = file.headings.iter().map(|heading| heading.name.as_str()).collect();
+    missing.iter()
+        .map(|path| {
+            let shared = unmatched.values()
+                .filter(|old| old.path == *path && old.heading.as_deref().is_some_and(|h| headings.contains(h)))
+                .count();
+            (shared, path)
+        })
+        .filter(|(shared, _)| *shared > 0 && *shared * 2 >= headings.len())
+        .max_by_key(|(shared, _)| *shared)
+        .map(|(_, path)| path.clone())
+}
+
+// Makes the changes in the plan. New headers are added under the note for the closest header above them,
+// and matched notes are moved there. Renamed headers rename their notes, keeping any prefix, as do
+// notes whose name is scoped to a note above that was renamed or that they moved away from
+pub fn apply_sync(note_map: &mut NoteMap, plan: &SyncPlan, files: &[SyncFile], prefix: &str) -> SyncReport {
+    let mut report = SyncReport::default();
+    // Names before anything is renamed, to tell which names are scoped to the note above
+    let old_names: HashMap<NoteId, String> = note_map.values().map(|note| (note.id, note.name.clone())).collect();
+    // Note for each header by its file and place, with the text of the header it was imported from
+    let mut placed: HashMap<(PathBuf, usize), (NoteId, String)> = HashMap::new();
+    for (id, source) in &plan.matched {
+        let Some(note) = note_map.get_mut(id) else {
+            continue;
+        };
+        if note.archived {
+            note.archived = false;
+            report.restored += 1;
+        }
+        let Some(old) = note.source.replace(source.clone()) else {
+            continue;
+        };
+        if old.path != source.path && source.position == 0 {
+            report.moved += 1;
+        }
+        placed.insert((source.path.clone(), source.position), (*id, source_text(&old)));
+    }
+
+    let added: HashSet<(&Path, usize)> = plan.added.iter()
+        .map(|source| (source.path.as_path(), source.position))
+        .collect();
+    for file in files {
+        // Level and note of each header above the current one, the file being level 0
+        let mut above: Vec<(usize, Option<NoteId>)> = vec![];
+        let levels = iter::once(0).chain(file.headings.iter().map(|heading| heading.level));
+        for (source, level) in file.sources().into_iter().zip(levels) {
+            while above.last().is_some_and(|(above_level, _)| *above_level >= level) {
+                above.pop();
+            }
+            let parent = above.iter().rev().find_map(|(_, id)| *id);
+            let id = match added.contains(&(source.path.as_path(), source.position)) {
+                true => add_note(note_map, file, source, parent, prefix, &mut report),
+                false => placed.get(&(source.path.clone(), source.position)).map(|(id, old_text)| {
+                    // What the name started with if it was scoped to the note it was under
+                    let old_scope = note_map.get(id)
+                        .and_then(|note| note.parent)
+                        .and_then(|old_parent| old_names.get(&old_parent))
+                        .filter(|_| !plan.keep_names.contains(id))
+                        .map(|old_parent_name| format!("{old_parent_name}{SCOPE_SEPARATOR}"));
+                    place_note(note_map, *id, &source, old_text, parent, old_scope, &mut report);
+                    *id
+                }),
+            };
+            above.push((level, id));
+        }
+    }
+    report
+}
+
+// Moves a matched note under the note now above it, then renames it if its header was renamed or its
+// name is scoped to a note above that changed. Names changed by hand no longer end with the header, so are kept.
+// Notes with nothing above them, like files, keep their parent
+fn place_note(note_map: &mut NoteMap, id: NoteId, source: &NoteSource, old_text: &str, parent: Option<NoteId>, old_scope: Option<String>, report: &mut SyncReport) {
+    let parent_name = parent.and_then(|parent| note_map.get(&parent)).map(|note| note.name.clone());
+    let Some(note) = note_map.get_mut(&id) else {
+        return;
+    };
+    if parent.is_some() {
+        note.parent = parent;
+    }
+    let Some(start) = note.name.strip_suffix(old_text) else {
+        return;
+    };
+    let start = match parent_name {
+        Some(parent_name) if old_scope.as_deref() == Some(start) => format!("{parent_name}{SCOPE_SEPARATOR}"),
+        _ => start.to_string(),
+    };
+    let new_name = format!("{start}{}", source_text(source));
+    if new_name == note.name {
+        return;
+    }
+    let old_name = note.name.clone();
+    match note_map.rename(&id, &new_name) {
+        Ok(name) => report.renamed.push((old_name, name)),
+        Err(e) => report.skipped.push(format!("{old_name} wasn't renamed, {e}")),
+    }
+}
+
+// Archives the given notes, leaving them out of reviews
+pub fn archive(note_map: &mut NoteMap, ids: &[NoteId]) {
+    for id in ids {
+        if let Some(note) = note_map.get_mut(id) {
+            note.archived = true;
+        }
+    }
+}
+
+// Adds a note for a new header, named after the note it sits under. If another note already has its name,
+// that note is used when it was never imported, otherwise the header is skipped
+fn add_note(note_map: &mut NoteMap, file: &SyncFile, source: NoteSource, parent: Option<NoteId>, prefix: &str, report: &mut SyncReport) -> Option<NoteId> {
+    let text = source_text(&source);
+    let parent_note = parent.and_then(|id| note_map.get(&id));
+    // A header named the same as the note it sits under, e.g. a title matching the file, is merged into it
+    let parent_text = parent_note.map(|note| note.source.as_ref().map_or(note.name.clone(), source_text));
+    if parent_text.is_some_and(|parent_text| name_key(&parent_text) == name_key(&text)) {
+        return parent;
+    }
+    let mut note = Note::new(scoped_name(prefix, parent_note.map(|note| note.name.as_str()), &text), 0, Local::now());
+    note.tags = file.tags.clone();
+    note.parent = parent;
+    note.source = Some(source.clone());
+    match note_map.insert(note) {
+        Ok(id) => {
+            report.added += 1;
+            Some(id)
+        },
+        Err(TrackerError::NameConflict(name)) => {
+            let existing = note_map.id_by_name(&name).and_then(|id| note_map.get_mut(&id))?;
+            if existing.source.is_none() {
+                existing.source = Some(source);
+                return Some(existing.id);
+            }
+            report.skipped.push(format!("{name} wasn't added, another note already has the name"));
+            None
+        },
+        Err(e) => {
+            report.skipped.push(format!("A header wasn't added, {e}"));
+            None
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str, headings: &[(&str, usize)]) -> SyncFile {
+        let path = PathBuf::from(path);
+        SyncFile {
+            name: path.file_stem().unwrap().to_string_lossy().to_string(),
+            path,
+            headings: headings.iter().enumerate()
+                .map(|(index, (name, level))| Heading { name: name.to_string(), level: *level, line: index + 1 })
+                .collect(),
+            tags: BTreeSet::new(),
+        }
+    }
+
+    // Syncs the files into the map, as the first import or a later sync
+    fn sync(note_map: &mut NoteMap, files: &[SyncFile]) -> (SyncPlan, SyncReport) {
+        let plan = plan_sync(note_map, Path::new("/notes"), files);
+        let report = apply_sync(note_map, &plan, files, "");
+        (plan, report)
+    }
+
+    fn imported() -> NoteMap {
+        let mut note_map = NoteMap::new();
+        sync(&mut note_map, &[file("/notes/graphs.md", &[("BFS", 1), ("Examples", 2), ("DFS", 1)])]);
+        note_map
+    }
+
+    fn parent_name(note_map: &NoteMap, name: &str) -> Option<String> {
+        let parent = note_map.get_by_name(name)?.parent?;
+        note_map.get(&parent).map(|note| note.name.clone())
+    }
+
+    #[test]
+    fn headers_are_added_under_the_one_above() {
+        let note_map = imported();
+        assert_eq!(note_map.len(), 4);
+        assert_eq!(parent_name(&note_map, "graphs › BFS › Examples").as_deref(), Some("graphs › BFS"));
+        assert_eq!(parent_name(&note_map, "graphs › DFS").as_deref(), Some("graphs"));
+    }
+
+    #[test]
+    fn renamed_headers_keep_their_notes() {
+        let mut note_map = imported();
+        let bfs = note_map.id_by_name("graphs › BFS").unwrap();
+        let examples = note_map.id_by_name("graphs › BFS › Examples").unwrap();
+        let (plan, report) = sync(&mut note_map, &[file("/notes/graphs.md", &[("Breadth first", 1), ("Examples", 2), ("DFS", 1)])]);
+        assert!(plan.added.is_empty() && plan.removed.is_empty());
+        assert_eq!(note_map.id_by_name("graphs › Breadth first"), Some(bfs));
+        // The header below is scoped to the renamed one
+        assert_eq!(note_map.id_by_name("graphs › Breadth first › Examples"), Some(examples));
+        assert_eq!(report.renamed.len(), 2);
+    }
+
+    #[test]
+    fn names_changed_by_hand_are_kept() {
+        let mut note_map = imported();
+        let bfs = note_map.id_by_name("graphs › BFS").unwrap();
+        note_map.rename(&bfs, "Breadth first search").unwrap();
+        sync(&mut note_map, &[file("/notes/graphs.md", &[("Breadth first", 1), ("Examples", 2), ("DFS", 1)])]);
+        assert_eq!(note_map.get(&bfs).unwrap().name, "Breadth first search");
+    }
+
+    #[test]
+    fn moved_headers_move_their_notes() {
+        let mut note_map = imported();
+        let examples = note_map.id_by_name("graphs › BFS › Examples").unwrap();
+        sync(&mut note_map, &[file("/notes/graphs.md", &[("BFS", 1), ("DFS", 1), ("Examples", 2)])]);
+        assert_eq!(note_map.get(&examples).unwrap().name, "graphs › DFS › Examples");
+        assert_eq!(parent_name(&note_map, "graphs › DFS › Examples").as_deref(), Some("graphs › DFS"));
+    }
+
+    #[test]
+    fn moved_files_keep_their_notes() {
+        let mut note_map = imported();
+        let ids: HashSet<NoteId> = note_map.ids().copied().collect();
+        let (plan, report) = sync(&mut note_map, &[file("/notes/algo/graphs.md", &[("BFS", 1), ("Examples", 2), ("DFS", 1)])]);
+        assert_eq!(report.moved, 1);
+        assert!(plan.added.is_empty() && plan.removed.is_empty());
+        assert_eq!(note_map.ids().copied().collect::<HashSet<NoteId>>(), ids);
+        let source = note_map.get_by_name("graphs › DFS").unwrap().source.clone().unwrap();
+        assert_eq!(source.path, PathBuf::from("/notes/algo/graphs.md"));
+    }
+
+    #[test]
+    fn renamed_files_are_matched_by_their_headers() {
+        let note_map = imported();
+        let plan = plan_sync(&note_map, Path::new("/notes"), &[file("/notes/search.md", &[("BFS", 1), ("DFS", 1)])]);
+        assert_eq!(plan.matched.len(), 3);
+        // Examples has gone
+        assert_eq!(plan.removed, [note_map.id_by_name("graphs › BFS › Examples").unwrap()]);
+        // Files sharing too few headers are new files, the file and each header being added
+        let plan = plan_sync(&note_map, Path::new("/notes"), &[file("/notes/trees.md", &[("BFS", 1), ("AVL", 1), ("Heaps", 1)])]);
+        assert_eq!(plan.added.len(), 4);
+        assert_eq!(plan.removed.len(), 4);
+    }
+
+    #[test]
+    fn removed_headers_are_returned_once_not_archived() {
+        let mut note_map = imported();
+        let dfs = note_map.id_by_name("graphs › DFS").unwrap();
+        let (plan, _) = sync(&mut note_map, &[file("/notes/graphs.md", &[("BFS", 1), ("Examples", 2)])]);
+        assert_eq!(plan.removed, [dfs]);
+        archive(&mut note_map, &plan.removed);
+        let (plan, report) = sync(&mut note_map, &[file("/notes/graphs.md", &[("BFS", 1), ("Examples", 2)])]);
+        assert!(plan.removed.is_empty());
+        assert_eq!(report.restored, 0);
+        let (_, report) = sync(&mut note_map, &[file("/notes/graphs.md", &[("BFS", 1), ("Examples", 2), ("DFS", 1)])]);
+        assert_eq!(report.restored, 1);
+        assert!(!note_map.get(&dfs).unwrap().archived);
+    }
+}
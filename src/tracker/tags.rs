@@ -0,0 +1,212 @@
+// Tags on notes, and the tag expressions used to filter notes by them.
+// Tags are lowercase with no spaces, so "Graph Theory" and "#graph-theory" are the same tag.
+// Expressions combine tags with AND, OR, NOT and brackets, e.g. "algo AND NOT graphs".
+// Tags next to each other without an operator are ANDed, so "algo NOT graphs" works too
+use std::collections::BTreeSet;
+
+use crate::storage::Note;
+use super::TrackerError;
+
+// Tidies a tag as it's saved, None if nothing is left
+pub fn clean_tag(tag: &str) -> Option<String> {
+    let tag = tag.trim().trim_start_matches('#')
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join("-")
+        .replace(['(', ')', ','], "")
+        .to_lowercase();
+    (!tag.is_empty()).then_some(tag)
+}
+
+// Parses tags separated by spaces or commas, e.g. "algo, #graphs"
+pub fn parse_tags(input: &str) -> BTreeSet<String> {
+    input.split(|c: char| c == ',' || c.is_whitespace())
+        .filter_map(clean_tag)
+        .collect()
+}
+
+// A parsed tag expression
+#[derive(Debug, Clone)]
+pub enum TagFilter {
+    // Matches every note, from an empty expression
+    All,
+    Tag(String),
+    Not(Box<TagFilter>),
+    And(Box<TagFilter>, Box<TagFilter>),
+    Or(Box<TagFilter>, Box<TagFilter>),
+}
+
+impl TagFilter {
+    // Parses an expression such as "(algo OR maths) AND NOT graphs".
+    // Operators can be any case
+    pub fn parse(expression: &str) -> Result<Self, TrackerError> {
+        let tokens = tokenize(expression);
+        if tokens.is_empty() {
+            return Ok(TagFilter::All);
+        }
+        let mut parser = Parser { tokens, position: 0 };
+        let filter = parser.or()?;
+        match parser.peek() {
+            Some(token) => Err(filter_error(format!("unexpected \"{token}\""))),
+            None => Ok(filter),
+        }
+    }
+
+    pub fn matches(&self, tags: &BTreeSet<String>) -> bool {
+        match self {
+            TagFilter::All => true,
+            TagFilter::Tag(tag) => tags.contains(tag),
+            TagFilter::Not(filter) => !filter.matches(tags),
+            TagFilter::And(a, b) => a.matches(tags) && b.matches(tags),
+            TagFilter::Or(a, b) => a.matches(tags) || b.matches(tags),
+        }
+    }
+
+    pub fn matches_note(&self, note: &Note) -> bool {
+        self.matches(&note.tags)
+    }
+}
+
+fn filter_error(reason: String) -> TrackerError {
+    TrackerError::InvalidTagFilter(reason)
+}
+
+// Splits an expression into words and brackets
+fn tokenize(expression: &str) -> Vec<String> {
+    expression.replace('(', " ( ").replace(')', " ) ")
+        .split_whitespace()
+        .map(|token| token.to_string())
+        .collect()
+}
+
+// Recursive descent over the tokens, NOT binding tightest and OR loosest
+struct Parser {
+    tokens: Vec<String>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.position).map(|token| token.as_str())
+    }
+
+    fn next(&mut self) -> Option<String> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    // Whether the next token is the given operator, taking it if so
+    fn take_operator(&mut self, operator: &str) -> bool {
+        let found = self.peek().is_some_and(|token| token.eq_ignore_ascii_case(operator));
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn or(&mut self) -> Result<TagFilter, TrackerError> {
+        let mut filter = self.and()?;
+        while self.take_operator("OR") {
+            filter = TagFilter::Or(Box::new(filter), Box::new(self.and()?));
+        }
+        Ok(filter)
+    }
+
+    fn and(&mut self) -> Result<TagFilter, TrackerError> {
+        let mut filter = self.not()?;
+        loop {
+            if self.take_operator("AND") {
+                filter = TagFilter::And(Box::new(filter), Box::new(self.not()?));
+                continue;
+            }
+            // Anything other than OR or a closing bracket starts another ANDed term
+            match self.peek() {
+                Some(token) if token != ")" && !token.eq_ignore_ascii_case("OR") => {
+                    filter = TagFilter::And(Box::new(filter), Box::new(self.not()?));
+                },
+                _ => return Ok(filter),
+            }
+        }
+    }
+
+    fn not(&mut self) -> Result<TagFilter, TrackerError> {
+        if self.take_operator("NOT") {
+            return Ok(TagFilter::Not(Box::new(self.not()?)));
+        }
+        self.term()
+    }
+
+    fn term(&mut self) -> Result<TagFilter, TrackerError> {
+        match self.next() {
+            Some(token) if token == "(" => {
+                let filter = self.or()?;
+                match self.next() {
+                    Some(token) if token == ")" => Ok(filter),
+                    _ => Err(filter_error("missing a closing bracket".to_string())),
+                }
+            },
+            Some(token) if token == ")" => Err(filter_error("unexpected \")\"".to_string())),
+            Some(token) if ["AND", "OR"].iter().any(|op| token.eq_ignore_ascii_case(op)) =>
+                Err(filter_error(format!("expected a tag before \"{token}\""))),
+            Some(token) => clean_tag(&token)
+                .map(TagFilter::Tag)
+                .ok_or(filter_error(format!("\"{token}\" isn't a tag"))),
+            None => Err(filter_error("expected a tag at the end".to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(tags: &[&str]) -> BTreeSet<String> {
+        tags.iter().map(|tag| tag.to_string()).collect()
+    }
+
+    fn matches(expression: &str, note_tags: &[&str]) -> bool {
+        TagFilter::parse(expression).unwrap().matches(&tags(note_tags))
+    }
+
+    #[test]
+    fn tags_are_cleaned() {
+        assert_eq!(clean_tag(" #Graph Theory "), Some("graph-theory".to_string()));
+        assert_eq!(clean_tag("#"), None);
+        assert_eq!(parse_tags("algo, #Graphs  maths"), tags(&["algo", "graphs", "maths"]));
+    }
+
+    #[test]
+    fn or_binds_looser_than_an_implicit_and() {
+        // a OR (b AND c)
+        assert!(matches("a OR b c", &["a"]));
+        assert!(matches("a OR b c", &["b", "c"]));
+        assert!(!matches("a OR b c", &["b"]));
+        assert!(!matches("a OR b c", &["c"]));
+        assert!(matches("(a OR b) c", &["b", "c"]));
+        assert!(!matches("(a OR b) c", &["a"]));
+    }
+
+    #[test]
+    fn not_binds_tightest() {
+        assert!(matches("NOT NOT a", &["a"]));
+        assert!(!matches("NOT NOT a", &[]));
+        // (NOT a) AND b
+        assert!(matches("NOT a b", &["b"]));
+        assert!(!matches("NOT a b", &["a", "b"]));
+        assert!(matches("algo and not graphs", &["algo"]));
+    }
+
+    #[test]
+    fn empty_expressions_match_everything() {
+        assert!(matches("", &[]));
+        assert!(matches("  ", &["a"]));
+    }
+
+    #[test]
+    fn unbalanced_brackets_error() {
+        for expression in ["a )", ")", "(a", "(a OR b", "a OR", "AND a", "NOT"] {
+            assert!(TagFilter::parse(expression).is_err(), "{expression} parsed");
+        }
+    }
+}